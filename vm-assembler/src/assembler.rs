@@ -1,7 +1,28 @@
-use crate::parser::{Element, Line, Operator};
+use crate::parser::{Element, Line, Operator, Spanned};
 use std::collections::HashMap;
+use std::fmt;
 use vm::prelude::*;
 
+/// Why a program couldn't be assembled.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AssembleError {
+    /// The source line, when it's known.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => self.message.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+#[derive(Default)]
 struct State<'a> {
     labels: HashMap<&'a str, Addr>,
     out: Vec<Byte>,
@@ -20,9 +41,9 @@ impl<'a> State<'a> {
         self.labels.insert(label, self.out.len() as Addr);
     }
 
-    fn evaluate(&mut self, element: Element) -> Vec<Byte> {
-        match element {
-            Element::Addr(addr) => self.evaluate(*addr),
+    fn evaluate(&mut self, element: Element<'a>) -> Result<Vec<Byte>, AssembleError> {
+        let bytes = match element {
+            Element::Addr(addr) => return self.evaluate(*addr),
             Element::Expr(expr) => {
                 let convert_to_short = |bytes: Vec<u8>| -> Short {
                     bytes
@@ -34,8 +55,8 @@ impl<'a> State<'a> {
                         })
                 };
 
-                let lhs = convert_to_short(self.evaluate(*expr.lhs));
-                let rhs = convert_to_short(self.evaluate(*expr.rhs));
+                let lhs = convert_to_short(self.evaluate(*expr.lhs)?);
+                let rhs = convert_to_short(self.evaluate(*expr.rhs)?);
 
                 let res = match expr.operator {
                     Operator::Add => lhs + rhs,
@@ -55,45 +76,115 @@ impl<'a> State<'a> {
             Element::Lit8(lit) => vec![ 0x00, (lit % 0x100) as Byte ],
             Element::Reg(reg) => vec![ reg.into() ],
             Element::Var(var) => {
-                let addr = self.labels.get(var).expect("label does not exist");
+                // labels are only known once they've been passed
+                let addr = self.labels.get(var).ok_or_else(|| AssembleError {
+                    line: None,
+                    message: format!("label `{}` is not defined before it's used", var),
+                })?;
 
                 vec![
                     (addr / 0x100) as Byte,
                     (addr % 0x100) as Byte,
                 ]
             }
-        }
+        };
+
+        Ok(bytes)
     }
 }
 
-impl<'a> Default for State<'a> {
-    fn default() -> Self {
-        Self {
-            labels: HashMap::new(),
-            out: Vec::new(),
-        }
-    }
+/// Where each instruction and label ended up in the assembled output, for
+/// debuggers and other tools that need to map addresses back to source.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DebugInfo {
+    /// `(address, source line)` for every instruction, in address order.
+    pub lines: Vec<(Addr, usize)>,
+    /// `(label, address)` in source order.
+    pub labels: Vec<(String, Addr)>,
 }
 
-pub fn assemble<'a>(parsed: Vec<Line<'a>>) -> Vec<Byte> {
-    let mut state = State::default();
+impl DebugInfo {
+    /// The source line of the instruction that covers `addr`.
+    pub fn line_for_addr(&self, addr: Addr) -> Option<usize> {
+        let i = match self.lines.binary_search_by_key(&addr, |(addr, _)| *addr) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
 
-    for line in parsed {
+        Some(self.lines[i].1)
+    }
+
+    /// The address of the first instruction on `line`.
+    pub fn addr_for_line(&self, line: usize) -> Option<Addr> {
+        self.lines.iter()
+            .find(|(_, l)| *l == line)
+            .map(|(addr, _)| *addr)
+    }
+
+    /// The closest label at or before `addr`.
+    pub fn label_for_addr(&self, addr: Addr) -> Option<&str> {
+        self.labels.iter()
+            .filter(|(_, label_addr)| *label_addr <= addr)
+            .max_by_key(|(_, label_addr)| *label_addr)
+            .map(|(label, _)| label.as_str())
+    }
+}
+
+impl<'a> State<'a> {
+    fn assemble_line(&mut self, line: Line<'a>) -> Result<(), AssembleError> {
         match line {
             Line::Instruction(instruction) => {
                 let variant = instruction.variant;
-                state.push_byte(variant.into());
+                self.push_byte(variant.into());
 
                 for argument in instruction.arguments {
-                    let bytes = state.evaluate(argument);
-                    state.push_bytes(bytes);
+                    let bytes = self.evaluate(argument)?;
+                    self.push_bytes(bytes);
                 }
             },
             Line::Label(label) => {
-                state.insert_label(label);
+                self.insert_label(label);
             },
+            Line::Comment(_) => {},
+        }
+
+        Ok(())
+    }
+}
+
+/// Assembles `parsed`, panicking on a label that isn't defined before it's
+/// used; `assemble_with_debug_info` reports that as an error instead.
+pub fn assemble(parsed: Vec<Line<'_>>) -> Vec<Byte> {
+    let mut state = State::default();
+
+    for line in parsed {
+        if let Err(err) = state.assemble_line(line) {
+            panic!("{}", err);
         }
     }
 
     state.out
 }
+
+pub fn assemble_with_debug_info(parsed: Vec<Spanned<Line<'_>>>) -> Result<(Vec<Byte>, DebugInfo), AssembleError> {
+    let mut state = State::default();
+    let mut debug_info = DebugInfo::default();
+
+    for line in parsed {
+        let addr = state.out.len() as Addr;
+
+        match line.value {
+            Line::Instruction(_) => debug_info.lines.push((addr, line.line)),
+            Line::Label(label) => debug_info.labels.push((label.into(), addr)),
+            Line::Comment(_) => {},
+        }
+
+        let number = line.line;
+
+        state.assemble_line(line.value)
+            .map_err(|err| AssembleError { line: Some(number), ..err })?;
+    }
+
+    Ok((state.out, debug_info))
+}
@@ -0,0 +1,182 @@
+use crate::parser::{parse_spanned, Element, Instruction, Line, Operator, Spanned};
+
+const TAB_WIDTH: usize = 8;
+
+/// One source line of formatted output.
+struct Row<'a> {
+    line: usize,
+    code: Option<String>,
+    instruction: bool,
+    comment: Option<&'a str>,
+}
+
+fn format_operator(operator: &Operator) -> &'static str {
+    match operator {
+        Operator::Add => " + ",
+        Operator::Sub => " - ",
+        Operator::Mul => " * ",
+    }
+}
+
+fn format_element(element: &Element, nested: bool) -> String {
+    match element {
+        Element::Addr(addr) => match &**addr {
+            Element::Lit(lit) | Element::Lit8(lit) => format!("&{:04X}", lit),
+            Element::Var(var) => format!("&[!{}]", var),
+            addr => format!("&{}", format_element(addr, false)),
+        },
+        Element::Expr(expr) => {
+            // expressions nest to the right, so only left hand sides need
+            // parentheses to parse back the same way
+            let mut out = format_element(&expr.lhs, true);
+            out.push_str(format_operator(&expr.operator));
+
+            let mut rhs = &*expr.rhs;
+            while let Element::Expr(expr) = rhs {
+                out.push_str(&format_element(&expr.lhs, true));
+                out.push_str(format_operator(&expr.operator));
+                rhs = &expr.rhs;
+            }
+
+            out.push_str(&format_element(rhs, true));
+
+            if nested {
+                format!("({})", out)
+            } else {
+                format!("[{}]", out)
+            }
+        },
+        Element::Lit(lit) | Element::Lit8(lit) => format!("${:04X}", lit),
+        Element::Reg(reg) => reg.as_str().into(),
+        Element::Var(var) => format!("!{}", var),
+    }
+}
+
+fn format_instruction(instruction: &Instruction) -> String {
+    let arguments: Vec<String> = instruction.arguments.iter()
+        .map(|argument| format_element(argument, false))
+        .collect();
+
+    if arguments.is_empty() {
+        format!("\t{}", instruction.variant.as_str())
+    } else {
+        format!("\t{} {}", instruction.variant.as_str(), arguments.join(", "))
+    }
+}
+
+fn width(code: &str) -> usize {
+    code.chars().fold(0, |width, char| match char {
+        '\t' => (width / TAB_WIDTH + 1) * TAB_WIDTH,
+        _ => width + 1,
+    })
+}
+
+fn rows<'a>(lines: &[Spanned<Line<'a>>]) -> Vec<Row<'a>> {
+    let mut rows: Vec<Row> = Vec::new();
+
+    for spanned in lines {
+        match &spanned.value {
+            Line::Comment(comment) => match rows.last_mut() {
+                Some(row) if row.line == spanned.line && row.code.is_some() => {
+                    row.comment = Some(comment);
+                },
+                _ => rows.push(Row {
+                    line: spanned.line,
+                    code: None,
+                    instruction: false,
+                    comment: Some(comment),
+                }),
+            },
+            Line::Instruction(instruction) => rows.push(Row {
+                line: spanned.line,
+                code: Some(format_instruction(instruction)),
+                instruction: true,
+                comment: None,
+            }),
+            Line::Label(label) => rows.push(Row {
+                line: spanned.line,
+                code: Some(format!("{}:", label)),
+                instruction: false,
+                comment: None,
+            }),
+        }
+    }
+
+    // comments on their own line are indented like the code after them
+    let mut instruction = false;
+    for row in rows.iter_mut().rev() {
+        if row.code.is_some() {
+            instruction = row.instruction;
+        } else {
+            row.instruction = instruction;
+        }
+    }
+
+    rows
+}
+
+/// Reprints parsed source in canonical form: one tab before instructions,
+/// lower-case mnemonics and registers, four-digit upper-case literals, at most
+/// one blank line in a row, and trailing comments aligned across consecutive
+/// lines.
+pub fn format_lines(lines: &[Spanned<Line<'_>>]) -> String {
+    let rows = rows(lines);
+    let mut out = String::new();
+
+    // the column that trailing comments start at, per row
+    let mut columns = vec![0; rows.len()];
+    let mut start = 0;
+    while start < rows.len() {
+        let mut end = start + 1;
+        if rows[start].code.is_some() && rows[start].comment.is_some() {
+            while end < rows.len() &&
+                rows[end].line == rows[end - 1].line + 1 &&
+                rows[end].code.is_some() &&
+                rows[end].comment.is_some()
+            {
+                end += 1;
+            }
+        }
+
+        let column = rows[start..end].iter()
+            .filter_map(|row| row.code.as_deref().map(width))
+            .max()
+            .unwrap_or(0) + 1;
+
+        columns[start..end].iter_mut().for_each(|col| *col = column);
+
+        start = end;
+    }
+
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 && row.line > rows[i - 1].line + 1 {
+            out.push('\n');
+        }
+
+        let code = match &row.code {
+            Some(code) => code.clone(),
+            None if row.instruction => "\t".into(),
+            None => String::new(),
+        };
+
+        out.push_str(&code);
+
+        if let Some(comment) = row.comment {
+            if row.code.is_some() {
+                out.push_str(&" ".repeat(columns[i] - width(&code)));
+            }
+
+            out.push(';');
+            out.push_str(comment.trim_end());
+        }
+
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Parses `input` and reprints it with [`format_lines`].
+pub fn format(input: &[u8]) -> pom::Result<String> {
+    parse_spanned(input).map(|lines| format_lines(&lines))
+}
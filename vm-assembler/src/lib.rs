@@ -1,5 +1,17 @@
 mod assembler;
+mod formatter;
 mod parser;
 
-pub use assembler::assemble;
-pub use parser::parse;
+pub use assembler::{assemble, assemble_with_debug_info, AssembleError, DebugInfo};
+pub use formatter::{format, format_lines};
+pub use parser::{
+    parse,
+    parse_spanned,
+    Element,
+    Expr,
+    Instruction,
+    Line,
+    Operator,
+    OperatorParseError,
+    Spanned,
+};
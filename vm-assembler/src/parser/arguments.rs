@@ -111,14 +111,14 @@ pub fn literal<'a>() -> Parser<'a, u8, Element<'a>> {
         (sym(b'$') * is_a(hex_digit).repeat(4))
             .convert(String::from_utf8)
             .convert(|hex| u16::from_str_radix(&hex, 16))
-            .map(|lit| Element::Lit(lit))
+            .map(Element::Lit)
     };
 
     let lit8 = || {
         (sym(b'$') * is_a(hex_digit).repeat(2))
             .convert(String::from_utf8)
             .convert(|hex| u16::from_str_radix(&hex, 16))
-            .map(|lit| Element::Lit8(lit))
+            .map(Element::Lit8)
     };
 
     (lit() | lit8()) - optional_whitespace()
@@ -144,5 +144,5 @@ pub fn register<'a>() -> Parser<'a, u8, Element<'a>> {
 }
 
 pub fn variable<'a>() -> Parser<'a, u8, Element<'a>> {
-    (sym(b'!') * identifier()).map(|var| Element::Var(var))
+    (sym(b'!') * identifier()).map(Element::Var)
 }
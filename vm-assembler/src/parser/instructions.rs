@@ -36,15 +36,11 @@ fn none<'a>() -> Parser<'a, u8, Vec<Element <'a>>> {
 }
 
 fn reg<'a>() -> Parser<'a, u8, Vec<Element <'a>>> {
-    (
-        (register() - optional_whitespace())
-    ).map(|reg| vec![ reg ])
+    (register() - optional_whitespace()).map(|reg| vec![ reg ])
 }
 
 fn lit<'a>() -> Parser<'a, u8, Vec<Element <'a>>> {
-    (
-        (element() - optional_whitespace())
-    ).map(|lit| vec![ lit ])
+    (element() - optional_whitespace()).map(|lit| vec![ lit ])
 }
 
 fn lit_reg<'a>() -> Parser<'a, u8, Vec<Element <'a>>> {
@@ -23,10 +23,19 @@ pub use instructions::Instruction;
 
 #[derive(Debug, PartialEq)]
 pub enum Line<'a> {
+    /// The text after a `;`, up to the end of the line.
+    Comment(&'a str),
     Instruction(Instruction<'a>),
     Label(&'a str),
 }
 
+fn comment<'a>() -> Parser<'a, u8, Line<'a>> {
+    (sym(b';') * none_of(b"\r\n").repeat(0..))
+        .collect()
+        .convert(std::str::from_utf8)
+        .map(|comment| Line::Comment(&comment[1..]))
+}
+
 fn line<'a>() -> Parser<'a, u8, Instruction<'a>> {
     instruction(InstructionVariant::MoveLitReg) |
     instruction(InstructionVariant::MoveRegReg) |
@@ -80,16 +89,55 @@ fn line<'a>() -> Parser<'a, u8, Instruction<'a>> {
     instruction(InstructionVariant::Halt)
 }
 
+/// A parsed item along with where it starts in the source. `line` is
+/// 1-based.
+#[derive(Debug, PartialEq)]
+pub struct Spanned<T> {
+    pub offset: usize,
+    pub line: usize,
+    pub value: T,
+}
+
 pub fn parse<'a>(input: &'a [u8]) -> pom::Result<Vec<Line<'a>>> {
+    parse_spanned(input)
+        .map(|lines| lines.into_iter().map(|line| line.value).collect())
+}
+
+pub fn parse_spanned<'a>(input: &'a [u8]) -> pom::Result<Vec<Spanned<Line<'a>>>> {
+    let blank_lines = || (optional_whitespace() * newline()).repeat(0..);
+
     let parser = || {
-        (
-            (identifier() - sym(b':') - optional_whitespace()).map(|s| Line::Label(s)) |
-            (sym(b'\t') * line()).map(|i| Line::Instruction(i))
-        ) - (
+        let statement = (
+            empty().pos() + (
+                (identifier() - sym(b':') - optional_whitespace()).map(Line::Label) |
+                (sym(b'\t') * line()).map(Line::Instruction)
+            )
+        ) + (optional_whitespace() * (empty().pos() + comment())).opt();
+
+        let standalone = (optional_whitespace() * (empty().pos() + comment()))
+            .map(|comment| (comment, None));
+
+        (statement | standalone) - (
             (optional_whitespace() * (newline() | end())) *
-            (optional_whitespace() * newline()).repeat(0..)
+            blank_lines()
         )
     };
 
-    (parser().repeat(0..) - end()).parse(input)
+    let lines = (blank_lines() * parser().repeat(0..) - end()).parse(input)?;
+    let lines = lines.into_iter()
+        .flat_map(|(statement, comment)| std::iter::once(statement).chain(comment));
+
+    let mut line = 1;
+    let mut counted = 0;
+
+    Ok(lines.map(|(offset, value)| {
+        line += input[counted..offset].iter().filter(|byte| **byte == b'\n').count();
+        counted = offset;
+
+        Spanned {
+            offset,
+            line,
+            value,
+        }
+    }).collect())
 }
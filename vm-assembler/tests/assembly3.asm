@@ -0,0 +1,14 @@
+start:  ; entry point
+
+
+	MOV $0a, &0050 ;counter
+; the loop body
+loop:
+	mov &0050, ACC   ; load
+	dec acc
+	Mov acc, &0050
+	jne $0000, &[!loop]   
+
+	; done
+end:
+	hlt
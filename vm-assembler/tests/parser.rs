@@ -1,5 +1,5 @@
 use vm::prelude::*;
-use vm_assembler::{assemble, parse};
+use vm_assembler::{assemble, assemble_with_debug_info, format, parse, parse_spanned, Line};
 
 // #[test]
 // fn bracketed_expr() {
@@ -313,3 +313,90 @@ fn assembler_vars() {
         InstructionVariant::Halt.into(),
     ]);
 }
+
+#[test]
+fn assembler_debug_info() {
+    let input = include_bytes!("assembly2.asm");
+    let res = parse_spanned(input)
+        .expect("coult not parse");
+
+    let (bytes, debug_info) = assemble_with_debug_info(res).unwrap();
+
+    assert_eq!(bytes, assemble(parse(input).unwrap()));
+
+    assert_eq!(debug_info.labels, vec![
+        ("start".to_string(), 0x0000),
+        ("loop".to_string(), 0x0005),
+        ("end".to_string(), 0x001A),
+    ]);
+
+    assert_eq!(debug_info.lines[0], (0x0000, 2));
+    assert_eq!(debug_info.lines[1], (0x0005, 5));
+    assert_eq!(debug_info.line_for_addr(0x0007), Some(5));
+    assert_eq!(debug_info.addr_for_line(13), None);
+    assert_eq!(debug_info.addr_for_line(14), Some(0x001A));
+    assert_eq!(debug_info.label_for_addr(0x0010), Some("loop"));
+}
+
+#[test]
+fn assembler_forward_label() {
+    let input = b"start:\n\tjne $0000, &[!end]\nend:\n\thlt\n";
+    let res = parse_spanned(input)
+        .expect("coult not parse");
+
+    let err = assemble_with_debug_info(res).unwrap_err();
+    assert_eq!(err.line, Some(2));
+    assert_eq!(err.to_string(), "line 2: label `end` is not defined before it's used");
+}
+
+#[test]
+fn comments() {
+    let input = include_bytes!("assembly3.asm");
+    let res = parse_spanned(input)
+        .expect("coult not parse");
+
+    let comments: Vec<(usize, &str)> = res.iter()
+        .filter_map(|line| match line.value {
+            Line::Comment(comment) => Some((line.line, comment)),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(comments, vec![
+        (1, " entry point"),
+        (4, "counter"),
+        (5, " the loop body"),
+        (7, " load"),
+        (12, " done"),
+    ]);
+
+}
+
+#[test]
+fn formatter() {
+    let input = include_bytes!("assembly3.asm");
+    let formatted = format(input)
+        .expect("coult not parse");
+
+    assert_eq!(formatted, "\
+start: ; entry point
+
+\tmov $000A, &0050 ;counter
+; the loop body
+loop:
+\tmov &0050, acc ; load
+\tdec acc
+\tmov acc, &0050
+\tjne $0000, &[!loop]
+
+; done
+end:
+\thlt
+");
+
+    assert_eq!(format(formatted.as_bytes()).unwrap(), formatted);
+    assert_eq!(
+        assemble(parse(formatted.as_bytes()).unwrap()),
+        assemble(parse(input).unwrap()),
+    );
+}
@@ -0,0 +1,198 @@
+//! `vm-bin coverage`: maps a `Coverage` recorded while running an assembled
+//! program back to its source, as an lcov tracefile or an annotated listing.
+
+use std::io::{self, Write};
+use vm::prelude::*;
+use vm_assembler::DebugInfo;
+
+fn is_conditional_jump(variant: InstructionVariant) -> bool {
+    matches!(
+        variant,
+        InstructionVariant::JumpNotEqReg |
+        InstructionVariant::JumpNotEqLit |
+        InstructionVariant::JumpEqReg |
+        InstructionVariant::JumpEqLit |
+        InstructionVariant::JumpLtReg |
+        InstructionVariant::JumpLtLit |
+        InstructionVariant::JumpGtReg |
+        InstructionVariant::JumpGtLit |
+        InstructionVariant::JumpLteReg |
+        InstructionVariant::JumpLteLit |
+        InstructionVariant::JumpGteReg |
+        InstructionVariant::JumpGteLit
+    )
+}
+
+/// `(line, count, branch)` for every instruction in `program`, in source
+/// order. `branch` is only `Some` for conditional jumps.
+fn lines(
+    coverage: &Coverage,
+    debug_info: &DebugInfo,
+    program: &[Byte],
+) -> Vec<(usize, u64, Option<BranchCoverage>)> {
+    let mut lines: Vec<_> = debug_info.lines.iter()
+        .map(|(addr, line)| {
+            let branch = program.get(*addr as usize)
+                .map(|opcode| InstructionVariant::from(*opcode))
+                .filter(|variant| is_conditional_jump(*variant))
+                .map(|_| coverage.branch(*addr).unwrap_or_default());
+
+            (*line, coverage.count(*addr), branch)
+        })
+        .collect();
+
+    lines.sort_by_key(|(line, _, _)| *line);
+    lines
+}
+
+pub fn write_lcov<W: Write>(
+    coverage: &Coverage,
+    debug_info: &DebugInfo,
+    program: &[Byte],
+    path: &str,
+    mut out: W,
+) -> io::Result<()> {
+    let lines = lines(coverage, debug_info, program);
+
+    writeln!(out, "TN:")?;
+    writeln!(out, "SF:{}", path)?;
+
+    let (mut found, mut hit) = (0, 0);
+    for (line, count, branch) in &lines {
+        let branch = match branch {
+            Some(branch) => branch,
+            None => continue,
+        };
+
+        // block 0, branch 0 is the jump being taken and branch 1 falling
+        // through; lcov wants `-` for branches on lines that never ran
+        let counts = match count {
+            0 => ["-".to_string(), "-".to_string()],
+            _ => [branch.taken.to_string(), branch.not_taken.to_string()],
+        };
+
+        for (i, taken) in counts.iter().enumerate() {
+            writeln!(out, "BRDA:{},0,{},{}", line, i, taken)?;
+
+            found += 1;
+            if taken != "-" && taken != "0" {
+                hit += 1;
+            }
+        }
+    }
+
+    writeln!(out, "BRF:{}", found)?;
+    writeln!(out, "BRH:{}", hit)?;
+
+    for (line, count, _) in &lines {
+        writeln!(out, "DA:{},{}", line, count)?;
+    }
+
+    writeln!(out, "LF:{}", lines.len())?;
+    writeln!(out, "LH:{}", lines.iter().filter(|(_, count, _)| *count > 0).count())?;
+    writeln!(out, "end_of_record")
+}
+
+/// The source with a gcov-style count column: how often each instruction ran,
+/// `#####` for instructions that never did, and `-` for lines without one.
+/// Conditional jumps get a second line saying which way they went.
+pub fn write_listing<W: Write>(
+    coverage: &Coverage,
+    debug_info: &DebugInfo,
+    program: &[Byte],
+    source: &str,
+    mut out: W,
+) -> io::Result<()> {
+    let lines = lines(coverage, debug_info, program);
+
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+
+        let found = lines.iter().find(|(l, _, _)| *l == line);
+
+        match found {
+            Some((_, 0, _)) => writeln!(out, "{:>9}: {}", "#####", text)?,
+            Some((_, count, _)) => writeln!(out, "{:>9}: {}", count, text)?,
+            None => writeln!(out, "{:>9}: {}", "-", text)?,
+        }
+
+        if let Some((_, count, Some(branch))) = found {
+            match count {
+                0 => writeln!(out, "{:>9}  branch never executed", "")?,
+                _ => writeln!(
+                    out,
+                    "{:>9}  branch taken {}, not taken {}",
+                    "",
+                    branch.taken,
+                    branch.not_taken,
+                )?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "start:\n\tmov $0002, acc\nloop:\n\tdec acc\n\tjne $0000, &[!loop]\n\thlt\n\tinc r1\n";
+
+    fn run() -> (Coverage, DebugInfo, Vec<Byte>) {
+        let parsed = vm_assembler::parse_spanned(SOURCE.as_bytes()).unwrap();
+        let (bytes, debug_info) = vm_assembler::assemble_with_debug_info(parsed).unwrap();
+
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(&bytes);
+
+        let mut cpu = Cpu::from(memory);
+        cpu.enable_coverage();
+        cpu.run();
+
+        (cpu.take_coverage().unwrap(), debug_info, bytes)
+    }
+
+    #[test]
+    fn can_write_lcov() {
+        let (coverage, debug_info, bytes) = run();
+
+        let mut out = Vec::new();
+        write_lcov(&coverage, &debug_info, &bytes, "loop.asm", &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+TN:
+SF:loop.asm
+BRDA:5,0,0,1
+BRDA:5,0,1,1
+BRF:2
+BRH:2
+DA:2,1
+DA:4,2
+DA:5,2
+DA:6,1
+DA:7,0
+LF:5
+LH:4
+end_of_record
+");
+    }
+
+    #[test]
+    fn can_write_listing() {
+        let (coverage, debug_info, bytes) = run();
+
+        let mut out = Vec::new();
+        write_listing(&coverage, &debug_info, &bytes, SOURCE, &mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "        -: start:
+        1: \tmov $0002, acc
+        -: loop:
+        2: \tdec acc
+        2: \tjne $0000, &[!loop]
+           branch taken 1, not taken 1
+        1: \thlt
+    #####: \tinc r1
+");
+    }
+}
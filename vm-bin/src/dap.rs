@@ -0,0 +1,594 @@
+//! A Debug Adapter Protocol server, so editors can launch and debug `.asm`
+//! programs directly.
+//!
+//! Requests are read on a separate thread so that a running program can still
+//! be paused; the program itself runs in chunks between messages.
+
+use crate::protocol::{read_message, write_message};
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{self, Read as IoRead, Write as IoWrite};
+use std::sync::mpsc::{self, TryRecvError};
+use vm::prelude::*;
+use vm_assembler::DebugInfo;
+
+/// Instructions to run between checks for new requests.
+const CHUNK: u64 = 0x1000;
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REF: u64 = 1;
+
+pub fn serve() -> Result<(), Box<dyn std::error::Error>> {
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut reader = stdin.lock();
+
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let stdout = io::stdout();
+    let mut adapter = Adapter::new(stdout.lock());
+
+    loop {
+        let message = if adapter.is_running() {
+            match rx.try_recv() {
+                Ok(message) => Some(message),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match rx.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            }
+        };
+
+        if let Some(message) = message {
+            if !adapter.handle(&message)? {
+                break;
+            }
+        }
+
+        if adapter.is_running() {
+            adapter.run_chunk()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Continue,
+    /// Stop once the call depth is at most this deep.
+    StepTo(usize),
+}
+
+struct Program {
+    cpu: Cpu,
+    debug_info: DebugInfo,
+    path: String,
+}
+
+pub struct Adapter<W: IoWrite> {
+    out: W,
+    seq: u64,
+    program: Option<Program>,
+    breakpoint_lines: Vec<u64>,
+    stop_on_entry: bool,
+    configured: bool,
+    mode: Option<Mode>,
+    resume: bool,
+}
+
+impl<W: IoWrite> Adapter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            seq: 0,
+            program: None,
+            breakpoint_lines: Vec::new(),
+            stop_on_entry: false,
+            configured: false,
+            mode: None,
+            resume: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.mode.is_some()
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+
+        write_message(&mut self.out, &message)
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    fn respond_error(&mut self, request: &Value, message: String) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+
+    fn stopped(&mut self, reason: &str) -> io::Result<()> {
+        self.mode = None;
+
+        self.event("stopped", json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        }))
+    }
+
+    fn exited(&mut self) -> io::Result<()> {
+        self.mode = None;
+
+        self.event("exited", json!({ "exitCode": 0 }))?;
+        self.event("terminated", json!({}))
+    }
+
+    /// Handles one request. Returns `false` once the client disconnects.
+    pub fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        if request["type"] != "request" {
+            return Ok(true);
+        }
+
+        let args = &request["arguments"];
+
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                self.respond(request, json!({
+                    "supportsConfigurationDoneRequest": true,
+                }))?;
+
+                self.event("initialized", json!({}))?;
+            },
+            "launch" => {
+                let path = args["program"].as_str().unwrap_or_default().to_string();
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+
+                match load(&path) {
+                    Ok(program) => {
+                        self.program = Some(program);
+                        self.apply_breakpoints();
+                        self.respond(request, json!({}))?;
+                        self.start()?;
+                    },
+                    Err(err) => self.respond_error(request, err)?,
+                }
+            },
+            "setBreakpoints" => {
+                let lines: Vec<u64> = args["breakpoints"].as_array()
+                    .map(|breakpoints| {
+                        breakpoints.iter()
+                            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                // Only the launched program has debug info, so breakpoints in any other
+                // source can't be placed and mustn't replace the program's own.
+                let other_source = self.program.as_ref()
+                    .is_some_and(|program| args["source"]["path"].as_str() != Some(program.path.as_str()));
+
+                let verified = if other_source {
+                    vec![false; lines.len()]
+                } else {
+                    self.breakpoint_lines = lines.clone();
+                    self.apply_breakpoints()
+                };
+
+                let breakpoints: Vec<Value> = lines.iter()
+                    .zip(verified)
+                    .map(|(line, verified)| json!({ "verified": verified, "line": line }))
+                    .collect();
+
+                self.respond(request, json!({ "breakpoints": breakpoints }))?;
+            },
+            "setExceptionBreakpoints" => self.respond(request, json!({}))?,
+            "configurationDone" => {
+                self.configured = true;
+                self.respond(request, json!({}))?;
+                self.start()?;
+            },
+            "threads" => {
+                self.respond(request, json!({
+                    "threads": [ { "id": THREAD_ID, "name": "main" } ],
+                }))?;
+            },
+            "stackTrace" => {
+                let frames = self.stack_frames();
+                let total = frames.len();
+
+                self.respond(request, json!({
+                    "stackFrames": frames,
+                    "totalFrames": total,
+                }))?;
+            },
+            "scopes" => {
+                self.respond(request, json!({
+                    "scopes": [ {
+                        "name": "Registers",
+                        "variablesReference": REGISTERS_REF,
+                        "expensive": false,
+                    } ],
+                }))?;
+            },
+            "variables" => {
+                let variables: Vec<Value> = match (&self.program, args["variablesReference"].as_u64()) {
+                    (Some(program), Some(REGISTERS_REF)) => RegisterVariant::VARIANTS.iter()
+                        .map(|reg| json!({
+                            "name": reg.as_str(),
+                            "value": format!("{:#06x}", program.cpu.register(*reg)),
+                            "variablesReference": 0,
+                        }))
+                        .collect(),
+                    _ => Vec::new(),
+                };
+
+                self.respond(request, json!({ "variables": variables }))?;
+            },
+            "continue" => {
+                self.respond(request, json!({ "allThreadsContinued": true }))?;
+                self.resume_with(Mode::Continue);
+            },
+            "next" => {
+                self.respond(request, json!({}))?;
+                let depth = self.depth();
+                self.resume_with(Mode::StepTo(depth));
+            },
+            "stepIn" => {
+                self.respond(request, json!({}))?;
+                self.resume_with(Mode::StepTo(usize::MAX));
+            },
+            "stepOut" => {
+                self.respond(request, json!({}))?;
+                let depth = self.depth().saturating_sub(1);
+                self.resume_with(Mode::StepTo(depth));
+            },
+            "pause" => {
+                self.respond(request, json!({}))?;
+                self.stopped("pause")?;
+            },
+            "disconnect" | "terminate" => {
+                self.respond(request, json!({}))?;
+                return Ok(false);
+            },
+            command => self.respond_error(request, format!("unsupported request `{}`", command))?,
+        }
+
+        Ok(true)
+    }
+
+    /// Starts the program once it's both launched and configured.
+    fn start(&mut self) -> io::Result<()> {
+        if self.program.is_none() || !self.configured {
+            return Ok(());
+        }
+
+        if self.stop_on_entry {
+            self.stopped("entry")
+        } else {
+            self.mode = Some(Mode::Continue);
+            self.resume = false;
+            Ok(())
+        }
+    }
+
+    fn resume_with(&mut self, mode: Mode) {
+        if self.program.is_some() {
+            self.mode = Some(mode);
+            self.resume = true;
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.program.as_ref()
+            .map_or(0, |program| program.cpu.frames().len())
+    }
+
+    fn apply_breakpoints(&mut self) -> Vec<bool> {
+        let program = match self.program.as_mut() {
+            Some(program) => program,
+            None => return vec![false; self.breakpoint_lines.len()],
+        };
+
+        let debug_info = &program.debug_info;
+        let debugger = program.cpu.debugger_mut();
+        let existing: Vec<Addr> = debugger.breakpoints().collect();
+        for addr in existing {
+            debugger.remove_breakpoint(addr);
+        }
+
+        self.breakpoint_lines.iter()
+            .map(|line| match debug_info.addr_for_line(*line as usize) {
+                Some(addr) => {
+                    debugger.add_breakpoint(addr);
+                    true
+                },
+                None => false,
+            })
+            .collect()
+    }
+
+    fn stack_frames(&self) -> Vec<Value> {
+        let program = match self.program.as_ref() {
+            Some(program) => program,
+            None => return Vec::new(),
+        };
+
+        let ip = program.cpu.register(RegisterVariant::Ip);
+
+        // return addresses point just past the `cal`, so look up the byte
+        // before to land on the calling line
+        let addrs = std::iter::once(ip)
+            .chain(program.cpu.frames().into_iter().map(|frame| frame.return_addr.wrapping_sub(1)));
+
+        addrs.enumerate()
+            .map(|(id, addr)| {
+                let name = program.debug_info.label_for_addr(addr)
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("{:#06x}", addr));
+
+                json!({
+                    "id": id,
+                    "name": name,
+                    "line": program.debug_info.line_for_addr(addr).unwrap_or(0),
+                    "column": 1,
+                    "source": { "path": program.path },
+                    "instructionPointerReference": format!("{:#06x}", addr),
+                })
+            })
+            .collect()
+    }
+
+    /// Runs the program for a bit, stopping on breakpoints, watchpoints,
+    /// faults, finished steps or halt.
+    pub fn run_chunk(&mut self) -> io::Result<()> {
+        let mode = match self.mode {
+            Some(mode) => mode,
+            None => return Ok(()),
+        };
+
+        let program = match self.program.as_mut() {
+            Some(program) => program,
+            None => return Ok(()),
+        };
+
+        let ip = program.cpu.register(RegisterVariant::Ip);
+        if !self.resume && program.cpu.debugger().has_breakpoint(ip) {
+            return self.stopped("breakpoint");
+        }
+
+        self.resume = false;
+
+        let depth = match mode {
+            Mode::Continue => {
+                let reason = program.cpu.run_for(CHUNK);
+                return self.stopped_by(reason);
+            },
+            Mode::StepTo(depth) => depth,
+        };
+
+        // `run_for` always runs the instruction at `ip`, so breakpoints past
+        // the first step are checked here
+        for i in 0..CHUNK {
+            let ip = program.cpu.register(RegisterVariant::Ip);
+            if i > 0 && program.cpu.debugger().has_breakpoint(ip) {
+                return self.stopped("breakpoint");
+            }
+
+            let reason = program.cpu.run_for(1);
+            if reason != StopReason::BudgetExhausted {
+                return self.stopped_by(reason);
+            }
+
+            if depth == usize::MAX || program.cpu.frames().len() <= depth {
+                return self.stopped("step");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reports why `run_for` stopped. Running out of instructions isn't a
+    /// stop, just the end of a chunk.
+    fn stopped_by(&mut self, reason: StopReason) -> io::Result<()> {
+        match reason {
+            StopReason::Halt => self.exited(),
+            StopReason::Breakpoint(_) => self.stopped("breakpoint"),
+            StopReason::Watch { .. } => self.stopped("data breakpoint"),
+            StopReason::Fault(fault) => {
+                self.mode = None;
+
+                self.event("stopped", json!({
+                    "reason": "exception",
+                    "description": "fault",
+                    "text": fault.to_string(),
+                    "threadId": THREAD_ID,
+                    "allThreadsStopped": true,
+                }))
+            },
+            StopReason::BudgetExhausted => Ok(()),
+        }
+    }
+}
+
+fn load(path: &str) -> Result<Program, String> {
+    let source = {
+        let mut buf = Vec::new();
+        let mut file = File::open(path).map_err(|err| format!("cannot open `{}`: {}", path, err))?;
+        file.read_to_end(&mut buf).map_err(|err| err.to_string())?;
+        buf
+    };
+
+    let parsed = vm_assembler::parse_spanned(&source).map_err(|err| err.to_string())?;
+    let (bytes, debug_info) = vm_assembler::assemble_with_debug_info(parsed).map_err(|err| err.to_string())?;
+
+    let mut memory = Memory::with_capacity(0x10000);
+    memory.set_bytes(&bytes);
+
+    Ok(Program {
+        cpu: Cpu::from(memory),
+        debug_info,
+        path: path.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(seq: u64, command: &str, arguments: Value) -> Value {
+        json!({
+            "seq": seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+    }
+
+    fn messages(out: &[u8]) -> Vec<Value> {
+        let mut reader = io::BufReader::new(out);
+        let mut messages = Vec::new();
+
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message);
+        }
+
+        messages
+    }
+
+    #[test]
+    fn can_stop_on_breakpoint() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../vm-assembler/tests/assembly2.asm");
+
+        let mut adapter = Adapter::new(Vec::new());
+
+        adapter.handle(&request(1, "initialize", json!({}))).unwrap();
+        adapter.handle(&request(2, "launch", json!({ "program": path }))).unwrap();
+        adapter.handle(&request(3, "setBreakpoints", json!({
+            "source": { "path": path },
+            "breakpoints": [ { "line": 8 }, { "line": 3 } ],
+        }))).unwrap();
+        adapter.handle(&request(4, "configurationDone", json!({}))).unwrap();
+
+        while adapter.is_running() {
+            adapter.run_chunk().unwrap();
+        }
+
+        adapter.handle(&request(5, "stackTrace", json!({ "threadId": 1 }))).unwrap();
+        adapter.handle(&request(6, "variables", json!({ "variablesReference": 1 }))).unwrap();
+
+        let messages = messages(&adapter.out);
+
+        let breakpoints = messages.iter()
+            .find(|message| message["command"] == "setBreakpoints")
+            .unwrap();
+        assert_eq!(breakpoints["body"]["breakpoints"], json!([
+            { "verified": true, "line": 8 },
+            { "verified": false, "line": 3 },
+        ]));
+
+        let stopped = messages.iter()
+            .find(|message| message["event"] == "stopped")
+            .unwrap();
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+
+        let stack = messages.iter()
+            .find(|message| message["command"] == "stackTrace")
+            .unwrap();
+        assert_eq!(stack["body"]["stackFrames"][0]["line"], 8);
+        assert_eq!(stack["body"]["stackFrames"][0]["name"], "loop");
+
+        let variables = messages.iter()
+            .find(|message| message["command"] == "variables")
+            .unwrap();
+        assert_eq!(variables["body"]["variables"][0]["value"], "0x000f");
+        assert_eq!(variables["body"]["variables"][1]["value"], "0x0009");
+    }
+
+    #[test]
+    fn cannot_set_breakpoints_in_other_source() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../vm-assembler/tests/assembly2.asm");
+
+        let mut adapter = Adapter::new(Vec::new());
+
+        adapter.handle(&request(1, "launch", json!({ "program": path }))).unwrap();
+        adapter.handle(&request(2, "setBreakpoints", json!({
+            "source": { "path": path },
+            "breakpoints": [ { "line": 8 } ],
+        }))).unwrap();
+        adapter.handle(&request(3, "setBreakpoints", json!({
+            "source": { "path": "other.asm" },
+            "breakpoints": [ { "line": 3 } ],
+        }))).unwrap();
+
+        assert_eq!(adapter.breakpoint_lines, vec![8]);
+
+        let messages = messages(&adapter.out);
+        let breakpoints: Vec<&Value> = messages.iter()
+            .filter(|message| message["command"] == "setBreakpoints")
+            .collect();
+        assert_eq!(breakpoints[1]["body"]["breakpoints"], json!([
+            { "verified": false, "line": 3 },
+        ]));
+    }
+
+    #[test]
+    fn can_stop_on_fault() {
+        let mut adapter = Adapter::new(Vec::new());
+        adapter.mode = Some(Mode::Continue);
+
+        adapter.stopped_by(StopReason::Fault(DeviceFault::new("unknown DMA command 0x0003"))).unwrap();
+        assert!(!adapter.is_running());
+
+        let messages = messages(&adapter.out);
+        assert_eq!(messages[0]["body"]["reason"], "exception");
+        assert_eq!(messages[0]["body"]["text"], "unknown DMA command 0x0003");
+    }
+
+    #[test]
+    fn cannot_launch_undefined_label() {
+        let path = std::env::temp_dir().join(format!("dap-undefined-label-{}.asm", std::process::id()));
+        std::fs::write(&path, "\tjne $0000, &[!end]\nend:\n\thlt\n").unwrap();
+
+        let mut adapter = Adapter::new(Vec::new());
+        adapter.handle(&request(1, "launch", json!({ "program": path }))).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let messages = messages(&adapter.out);
+        assert_eq!(messages[0]["success"], false);
+        assert_eq!(messages[0]["message"], "line 1: label `end` is not defined before it's used");
+    }
+}
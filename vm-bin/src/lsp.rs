@@ -0,0 +1,512 @@
+//! A Language Server Protocol server for the assembly dialect: diagnostics,
+//! go-to-definition and references for labels, hover for instructions and
+//! registers, and completion.
+//!
+//! Columns are reported in bytes, which matches the UTF-16 offsets editors
+//! expect as long as the source is ASCII.
+
+use crate::protocol::{read_message, write_message};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use vm::prelude::*;
+use vm_assembler::{Line, Spanned};
+
+const ERROR: u64 = 1;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+pub fn serve() -> Result<(), Box<dyn std::error::Error>> {
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+
+    let stdout = io::stdout();
+    let mut server = Server::new(stdout.lock());
+
+    while let Some(message) = read_message(&mut reader)? {
+        if !server.handle(&message)? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SymbolKind {
+    Definition,
+    Reference,
+}
+
+/// A label name as it appears in the source. `line` and the columns are
+/// 0-based.
+#[derive(Clone, Debug, PartialEq)]
+struct Symbol<'a> {
+    name: &'a str,
+    kind: SymbolKind,
+    line: usize,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Symbol<'a> {
+    fn range(&self) -> Value {
+        json!({
+            "start": { "line": self.line, "character": self.start },
+            "end": { "line": self.line, "character": self.end },
+        })
+    }
+}
+
+fn is_ident_start(byte: u8) -> bool {
+    byte.is_ascii_alphabetic() || byte == b'_'
+}
+
+fn is_ident(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// Finds every label definition (`loop:`) and reference (`!loop`).
+fn symbols(text: &str) -> Vec<Symbol<'_>> {
+    let mut symbols = Vec::new();
+
+    for (line, content) in text.lines().enumerate() {
+        // nothing after a `;` is code
+        let content = content.split(';').next().unwrap_or_default();
+        let bytes = content.as_bytes();
+
+        // definitions have to start the line
+        if bytes.first().copied().is_some_and(is_ident_start) {
+            let end = bytes.iter().position(|byte| !is_ident(*byte)).unwrap_or(bytes.len());
+            if bytes.get(end) == Some(&b':') {
+                symbols.push(Symbol {
+                    name: &content[..end],
+                    kind: SymbolKind::Definition,
+                    line,
+                    start: 0,
+                    end,
+                });
+            }
+        }
+
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'!' && bytes.get(i + 1).copied().is_some_and(is_ident_start) {
+                let start = i + 1;
+                let end = bytes[start..].iter()
+                    .position(|byte| !is_ident(*byte))
+                    .map_or(bytes.len(), |len| start + len);
+
+                symbols.push(Symbol {
+                    name: &content[start..end],
+                    kind: SymbolKind::Reference,
+                    line,
+                    start,
+                    end,
+                });
+
+                i = end;
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    symbols
+}
+
+/// The identifier-ish word under the cursor, with its column range.
+fn word_at(text: &str, line: usize, character: usize) -> Option<(&str, usize, usize)> {
+    let content = text.lines().nth(line)?;
+    let bytes = content.as_bytes();
+
+    let mut start = character.min(bytes.len());
+    while start > 0 && is_ident(bytes[start - 1]) {
+        start -= 1;
+    }
+
+    let mut end = character.min(bytes.len());
+    while end < bytes.len() && is_ident(bytes[end]) {
+        end += 1;
+    }
+
+    if start == end {
+        return None;
+    }
+
+    Some((&content[start..end], start, end))
+}
+
+fn position_of(text: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(text.len());
+    let before = &text.as_bytes()[..offset];
+
+    let line = before.iter().filter(|byte| **byte == b'\n').count();
+    let column = before.iter().rev().take_while(|byte| **byte != b'\n').count();
+
+    (line, column)
+}
+
+fn diagnostic(line: usize, start: usize, end: usize, message: String) -> Value {
+    json!({
+        "range": {
+            "start": { "line": line, "character": start },
+            "end": { "line": line, "character": end },
+        },
+        "severity": ERROR,
+        "source": "vm-assembler",
+        "message": message,
+    })
+}
+
+fn diagnostics(text: &str) -> Vec<Value> {
+    let mut diagnostics = Vec::new();
+
+    if let Err(err) = vm_assembler::parse(text.as_bytes()) {
+        let offset = match &err {
+            pom::Error::Mismatch { position, .. } |
+            pom::Error::Conversion { position, .. } |
+            pom::Error::Expect { position, .. } |
+            pom::Error::Custom { position, .. } => *position,
+            pom::Error::Incomplete => text.len(),
+        };
+
+        let (line, _) = position_of(text, offset);
+        let len = text.lines().nth(line).map_or(0, str::len);
+
+        diagnostics.push(diagnostic(line, 0, len, format!("cannot parse line: {}", err)));
+    }
+
+    let symbols = symbols(text);
+
+    for reference in symbols.iter().filter(|symbol| symbol.kind == SymbolKind::Reference) {
+        let definition = symbols.iter().find(|symbol| {
+            symbol.kind == SymbolKind::Definition && symbol.name == reference.name
+        });
+
+        let message = match definition {
+            None => format!("label `{}` is not defined", reference.name),
+            // the assembler resolves labels in a single pass
+            Some(definition) if definition.line > reference.line => {
+                format!("label `{}` is used before it is defined", reference.name)
+            },
+            Some(_) => continue,
+        };
+
+        diagnostics.push(diagnostic(reference.line, reference.start, reference.end, message));
+    }
+
+    diagnostics
+}
+
+fn describe_variant(variant: InstructionVariant) -> String {
+    let arguments = InstructionArguments::from(variant);
+    let opcode: Byte = variant.into();
+
+    format!(
+        "`{}` — `{:?}`: opcode `{:#04x}`, operands `{:?}`, {} bytes",
+        variant.as_str(),
+        variant,
+        opcode,
+        arguments,
+        1 + arguments.bytes(),
+    )
+}
+
+fn hover(text: &str, line: usize, character: usize) -> Option<String> {
+    let (word, _, _) = word_at(text, line, character)?;
+    let lower = word.to_lowercase();
+
+    if let Some(reg) = RegisterVariant::VARIANTS.iter().find(|reg| reg.as_str() == lower) {
+        let code: Byte = (*reg).into();
+        return Some(format!("register `{}` (`{:#04x}`)", reg.as_str(), code));
+    }
+
+    let symbols = symbols(text);
+    let label = symbols.iter().find(|symbol| {
+        symbol.line == line && symbol.start <= character && character <= symbol.end
+    });
+
+    if let Some(label) = label {
+        return Some(match label_addr(text, label.name) {
+            Some(addr) => format!("label `{}` at `{:#06x}`", label.name, addr),
+            None => format!("label `{}`", label.name),
+        });
+    }
+
+    let variants: Vec<InstructionVariant> = InstructionVariant::VARIANTS.iter()
+        .copied()
+        .filter(|variant| variant.as_str() == lower)
+        .collect();
+
+    if variants.is_empty() {
+        return None;
+    }
+
+    // prefer the exact encoding if the line parses
+    let parsed = vm_assembler::parse_spanned(text.as_bytes()).ok()
+        .and_then(|lines| {
+            lines.into_iter().find_map(|spanned: Spanned<Line>| match spanned.value {
+                Line::Instruction(instruction) if spanned.line == line + 1 => Some(instruction.variant),
+                _ => None,
+            })
+        });
+
+    match parsed {
+        Some(variant) => Some(describe_variant(variant)),
+        None => Some(variants.into_iter().map(describe_variant).collect::<Vec<_>>().join("\n\n")),
+    }
+}
+
+fn label_addr(text: &str, label: &str) -> Option<Addr> {
+    if !diagnostics(text).is_empty() {
+        return None;
+    }
+
+    let parsed = vm_assembler::parse_spanned(text.as_bytes()).ok()?;
+    let (_, debug_info) = vm_assembler::assemble_with_debug_info(parsed).ok()?;
+
+    debug_info.labels.iter()
+        .find(|(name, _)| name == label)
+        .map(|(_, addr)| *addr)
+}
+
+fn completions(text: &str, line: usize, character: usize) -> Vec<Value> {
+    let after_bang = text.lines().nth(line)
+        .and_then(|content| {
+            let (_, start, _) = word_at(text, line, character).unwrap_or(("", character, character));
+            content.as_bytes().get(start.checked_sub(1)?).copied()
+        }) == Some(b'!');
+
+    let mut items = Vec::new();
+
+    let mut labels: Vec<&str> = symbols(text).into_iter()
+        .filter(|symbol| symbol.kind == SymbolKind::Definition)
+        .map(|symbol| symbol.name)
+        .collect();
+    labels.sort_unstable();
+    labels.dedup();
+
+    for label in labels {
+        items.push(json!({ "label": label, "kind": 18, "detail": "label" }));
+    }
+
+    if after_bang {
+        return items;
+    }
+
+    let mut mnemonics: Vec<&str> = InstructionVariant::VARIANTS.iter()
+        .map(InstructionVariant::as_str)
+        .collect();
+    mnemonics.sort_unstable();
+    mnemonics.dedup();
+
+    for mnemonic in mnemonics {
+        items.push(json!({ "label": mnemonic, "kind": 14, "detail": "instruction" }));
+    }
+
+    for reg in RegisterVariant::VARIANTS.iter() {
+        items.push(json!({ "label": reg.as_str(), "kind": 6, "detail": "register" }));
+    }
+
+    items
+}
+
+pub struct Server<W: Write> {
+    out: W,
+    documents: HashMap<String, String>,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            documents: HashMap::new(),
+        }
+    }
+
+    fn respond(&mut self, id: &Value, result: Value) -> io::Result<()> {
+        write_message(&mut self.out, &json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": result,
+        }))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        write_message(&mut self.out, &json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }))
+    }
+
+    fn publish(&mut self, uri: &str) -> io::Result<()> {
+        let diagnostics = self.documents.get(uri)
+            .map(|text| diagnostics(text))
+            .unwrap_or_default();
+
+        self.notify("textDocument/publishDiagnostics", json!({
+            "uri": uri,
+            "diagnostics": diagnostics,
+        }))
+    }
+
+    /// Handles one message. Returns `false` once the client asks to exit.
+    pub fn handle(&mut self, message: &Value) -> io::Result<bool> {
+        let id = &message["id"];
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+
+        let text = self.documents.get(&uri).cloned().unwrap_or_default();
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+
+        match message["method"].as_str().unwrap_or_default() {
+            "initialize" => {
+                self.respond(id, json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "hoverProvider": true,
+                        "definitionProvider": true,
+                        "referencesProvider": true,
+                        "completionProvider": { "triggerCharacters": [ "!" ] },
+                    },
+                    "serverInfo": { "name": "vm-bin lsp" },
+                }))?;
+            },
+            "shutdown" => self.respond(id, Value::Null)?,
+            "exit" => return Ok(false),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.into());
+                self.publish(&uri)?;
+            },
+            "textDocument/didChange" => {
+                // we only advertise full document sync
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|changes| changes.last()).and_then(|change| change["text"].as_str()) {
+                    self.documents.insert(uri.clone(), text.into());
+                }
+
+                self.publish(&uri)?;
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish(&uri)?;
+            },
+            "textDocument/hover" => {
+                let result = match hover(&text, line, character) {
+                    Some(contents) => json!({
+                        "contents": { "kind": "markdown", "value": contents },
+                    }),
+                    None => Value::Null,
+                };
+
+                self.respond(id, result)?;
+            },
+            "textDocument/definition" => {
+                let symbols = symbols(&text);
+                let result = word_at(&text, line, character)
+                    .and_then(|(word, _, _)| {
+                        symbols.iter().find(|symbol| symbol.kind == SymbolKind::Definition && symbol.name == word)
+                    })
+                    .map_or(Value::Null, |symbol| json!({ "uri": uri, "range": symbol.range() }));
+
+                self.respond(id, result)?;
+            },
+            "textDocument/references" => {
+                let include_declaration = params["context"]["includeDeclaration"].as_bool().unwrap_or(true);
+                let symbols = symbols(&text);
+
+                let locations: Vec<Value> = match word_at(&text, line, character) {
+                    Some((word, _, _)) => symbols.iter()
+                        .filter(|symbol| symbol.name == word)
+                        .filter(|symbol| include_declaration || symbol.kind == SymbolKind::Reference)
+                        .map(|symbol| json!({ "uri": uri, "range": symbol.range() }))
+                        .collect(),
+                    None => Vec::new(),
+                };
+
+                self.respond(id, json!(locations))?;
+            },
+            "textDocument/completion" => {
+                let items = completions(&text, line, character);
+                self.respond(id, json!(items))?;
+            },
+            method => {
+                // requests need an answer; notifications can be ignored
+                if !id.is_null() {
+                    write_message(&mut self.out, &json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": METHOD_NOT_FOUND,
+                            "message": format!("unsupported method `{}`", method),
+                        },
+                    }))?;
+                }
+            },
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "start:\n\tmov $0A, &0050\n\nloop:\n\tdec acc\n\tjne $00, &[!loop]\n\tjne $00, &[!end]\n\nend:\n\thlt\n";
+
+    #[test]
+    fn can_find_labels() {
+        let symbols = symbols(SOURCE);
+
+        assert_eq!(symbols.len(), 5);
+        assert_eq!(symbols[2], Symbol {
+            name: "loop",
+            kind: SymbolKind::Reference,
+            line: 5,
+            start: 13,
+            end: 17,
+        });
+    }
+
+    #[test]
+    fn can_diagnose() {
+        let diagnostics = diagnostics(SOURCE);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["message"], "label `end` is used before it is defined");
+        assert_eq!(diagnostics[0]["range"]["start"], json!({ "line": 6, "character": 13 }));
+
+        let diagnostics = super::diagnostics("start:\n\tmov $0A, &0050\n\tbogus r1\n");
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 2);
+    }
+
+    #[test]
+    fn can_hover() {
+        let text = "start:\n\tmov $0A, &0050\n\tmov r1, acc\n";
+
+        assert_eq!(
+            hover(text, 1, 2).unwrap(),
+            "`mov` — `MoveLitMem`: opcode `0x1b`, operands `LitMem`, 5 bytes",
+        );
+        assert_eq!(hover(text, 2, 10).unwrap(), "register `acc` (`0x01`)");
+        assert_eq!(hover(text, 0, 1).unwrap(), "label `start` at `0x0000`");
+    }
+
+    #[test]
+    fn can_complete_each_label_once() {
+        let text = "start:\n\thlt\nend:\n\thlt\nstart:\n\thlt\n";
+
+        let labels: Vec<Value> = completions(text, 1, 1).into_iter()
+            .filter(|item| item["detail"] == "label")
+            .map(|item| item["label"].clone())
+            .collect();
+
+        assert_eq!(labels, vec![ json!("end"), json!("start") ]);
+    }
+}
@@ -2,7 +2,17 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::path::PathBuf;
 use structopt::StructOpt;
+use vm::gdb::SessionEnd;
 
+mod coverage;
+mod dap;
+mod lsp;
+mod profile;
+mod protocol;
+mod uart;
+
+// parsed once, so `Run` being much bigger than the rest doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, StructOpt)]
 enum Options {
     #[structopt(about = "Convert the given assembly file to machine code")]
@@ -15,6 +25,13 @@ enum Options {
         )]
         out: PathBuf,
 
+        #[structopt(
+            about = "Path to write label addresses to, for `profile --symbols`",
+            long,
+            parse(from_os_str),
+        )]
+        symbols: Option<PathBuf>,
+
         #[structopt(
             name = "FILE",
             about = "Assembly input to read",
@@ -22,6 +39,95 @@ enum Options {
         )]
         file: PathBuf,
     },
+    #[structopt(about = "Assemble and run a program, then print its source annotated with coverage")]
+    Coverage {
+        #[structopt(
+            about = "Give up after running this many instructions",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        max_steps: Option<u64>,
+
+        #[structopt(
+            about = "Path to write an lcov tracefile to",
+            long,
+            parse(from_os_str),
+        )]
+        lcov: Option<PathBuf>,
+
+        #[structopt(
+            name = "FILE",
+            about = "Assembly input to read",
+            parse(from_os_str),
+        )]
+        file: PathBuf,
+    },
+    #[structopt(about = "Serve the Debug Adapter Protocol over stdio")]
+    Dap,
+    #[structopt(about = "Reformat assembly files in place")]
+    Fmt {
+        #[structopt(
+            about = "Only report files that are not formatted, and fail if there are any",
+            long,
+        )]
+        check: bool,
+
+        #[structopt(
+            name = "FILE",
+            about = "Assembly files to format",
+            parse(from_os_str),
+            required = true,
+        )]
+        files: Vec<PathBuf>,
+    },
+    #[structopt(about = "Serve the Language Server Protocol for assembly files over stdio")]
+    Lsp,
+    #[structopt(about = "Run a binary and report how many instructions ran where")]
+    Profile {
+        #[structopt(
+            about = "How much memory to give the VM",
+            short,
+            long = "memory",
+            default_value = "0x10000",
+            parse(try_from_str = parse_int::parse),
+        )]
+        memory_capacity: usize,
+
+        #[structopt(
+            about = "Give up after running this many instructions",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        max_steps: Option<u64>,
+
+        #[structopt(
+            about = "Symbol file from `assemble --symbols`, to name addresses by label",
+            long,
+            parse(from_os_str),
+        )]
+        symbols: Option<PathBuf>,
+
+        #[structopt(
+            about = "Path to write folded stacks to, for flame graph tools",
+            long,
+            parse(from_os_str),
+        )]
+        folded: Option<PathBuf>,
+
+        #[structopt(
+            about = "How many of the hottest addresses to list",
+            long,
+            default_value = "20",
+        )]
+        top: usize,
+
+        #[structopt(
+            name = "FILE",
+            about = "Binary input to read",
+            parse(from_os_str),
+        )]
+        file: PathBuf,
+    },
     #[structopt(about = "Run a binary")]
     Run {
         #[structopt(
@@ -33,6 +139,188 @@ enum Options {
         )]
         memory_capacity: usize,
 
+        #[structopt(
+            about = "Load this file into the memory banks, filling bank 0 first",
+            long,
+            parse(from_os_str),
+            requires = "banked",
+        )]
+        bank_image: Option<PathBuf>,
+
+        #[structopt(
+            about = "How many bytes of the address space the memory banks share",
+            long,
+            default_value = "0x2000",
+            parse(try_from_str = parse_int::parse),
+        )]
+        bank_size: usize,
+
+        #[structopt(
+            about = "Map a window onto switchable memory banks at this address, with the bank register after it",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        banked: Option<u16>,
+
+        #[structopt(
+            about = "How many memory banks there are",
+            long,
+            default_value = "4",
+            parse(try_from_str = parse_int::parse),
+        )]
+        banks: usize,
+
+        #[structopt(
+            about = "Print how many cycles the program took once it stops",
+            long,
+        )]
+        cycles: bool,
+
+        #[structopt(
+            about = "Map a 128x128 pixel display at this address",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        display: Option<u16>,
+
+        #[structopt(
+            about = "Map a disk controller at this address",
+            long,
+            parse(try_from_str = parse_int::parse),
+            requires = "disk-image",
+        )]
+        disk: Option<u16>,
+
+        #[structopt(
+            about = "The disk image the disk controller reads and writes",
+            long,
+            parse(from_os_str),
+        )]
+        disk_image: Option<PathBuf>,
+
+        #[structopt(
+            about = "Write the display to this PNG (or .ppm) file once the program stops",
+            long,
+            parse(from_os_str),
+        )]
+        display_out: Option<PathBuf>,
+
+        #[structopt(
+            about = "Map a DMA controller at this address",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        dma: Option<u16>,
+
+        #[structopt(
+            about = "Write every frame the program presents to this directory, as numbered PNG files",
+            long,
+            parse(from_os_str),
+        )]
+        frames: Option<PathBuf>,
+
+        #[structopt(
+            about = "Wait for a gdb remote connection on this address before running",
+            long,
+        )]
+        gdb: Option<String>,
+
+        #[structopt(
+            about = "Keep the screen in memory and print its text once the program stops",
+            long,
+        )]
+        headless: bool,
+
+        #[structopt(
+            about = "Address of the subroutine to call when a device interrupts",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        interrupt_handler: Option<u16>,
+
+        #[structopt(
+            about = "Map a keyboard reading from stdin at this address",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        keyboard: Option<u16>,
+
+        #[structopt(
+            about = "Give up after running this many instructions",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        max_steps: Option<u64>,
+
+        #[structopt(
+            about = "Map a random number generator at this address",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        rng: Option<u16>,
+
+        #[structopt(
+            about = "Load the program into read-only memory, so writing over it faults; RAM covers the rest",
+            long,
+        )]
+        rom: bool,
+
+        #[structopt(
+            about = "Map a real-time clock at this address",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        rtc: Option<u16>,
+
+        #[structopt(
+            about = "Map a 16x16 character screen at this address",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        screen: Option<u16>,
+
+        #[structopt(
+            about = "Seed the random number generator with this, so runs can be repeated",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        seed: Option<u64>,
+
+        #[structopt(
+            about = "Map a serial port at this address",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        uart: Option<u16>,
+
+        #[structopt(
+            about = "What the serial port is connected to: stdio, file:IN,OUT, tcp:HOST:PORT or unix:PATH",
+            long,
+            default_value = "stdio",
+        )]
+        uart_backend: uart::Backend,
+
+        #[structopt(
+            about = "Run the clock from 1970 at this many cycles per second rather than by the host",
+            long,
+            parse(try_from_str = parse_int::parse),
+        )]
+        virtual_clock: Option<u64>,
+
+        #[structopt(
+            about = "Restore machine state from a snapshot before running",
+            long,
+            parse(from_os_str),
+        )]
+        load_state: Option<PathBuf>,
+
+        #[structopt(
+            about = "Write a snapshot of the machine state once the program stops",
+            long,
+            parse(from_os_str),
+        )]
+        save_state: Option<PathBuf>,
+
         #[structopt(
             name = "FILE",
             about = "Binary input to read",
@@ -42,12 +330,31 @@ enum Options {
     },
 }
 
+/// Maps `device` at `addr`, covering the `len` bytes it answers to.
+fn map_device(
+    mm: &mut vm::prelude::MemoryMapper,
+    addr: u16,
+    priority: u8,
+    device: Box<dyn vm::prelude::Device>,
+    len: usize,
+) -> Result<(), vm::prelude::MemoryMapperError> {
+    mm.add_region(
+        vm::prelude::MemoryRegion::builder()
+            .range(addr as usize..=addr as usize + len - 1)
+            .priority(priority)
+            .device(device)
+            .finalize()
+            .unwrap(),
+    )
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let options = Options::from_args();
 
     match options {
         Options::Assemble {
             out,
+            symbols,
             file,
         } => {
             let bytes = {
@@ -57,12 +364,16 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 buf
             };
 
-            let parsed = vm_assembler::parse(&bytes)?;
-            let assembled = vm_assembler::assemble(parsed);
+            let parsed = vm_assembler::parse_spanned(&bytes)?;
+            let (assembled, debug_info) = vm_assembler::assemble_with_debug_info(parsed)?;
+
+            if let Some(path) = symbols {
+                profile::Symbols::write_to(&debug_info.labels, File::create(path)?)?;
+            }
 
             let mut outfile = File::create(out.clone())?;
 
-            outfile.write(&assembled)?;
+            outfile.write_all(&assembled)?;
 
             if cfg!(unix) {
                 use std::os::unix::fs::PermissionsExt;
@@ -74,10 +385,140 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 std::fs::set_permissions(out.clone(), perms)?;
             }
         },
+        Options::Coverage {
+            max_steps,
+            lcov,
+            file,
+        } => {
+            use vm::prelude::*;
+
+            let source = std::fs::read_to_string(&file)?;
+
+            let parsed = vm_assembler::parse_spanned(source.as_bytes())?;
+            let (bytes, debug_info) = vm_assembler::assemble_with_debug_info(parsed)?;
+
+            let mut memory = Memory::with_capacity(0x10000);
+            memory.set_bytes(&bytes);
+
+            let mut cpu = Cpu::from(memory);
+            cpu.enable_coverage();
+
+            match max_steps {
+                Some(max_steps) => cpu.run_for(max_steps),
+                None => cpu.run(),
+            };
+
+            let coverage = cpu.take_coverage().unwrap();
+
+            if let Some(path) = lcov {
+                let out = File::create(path)?;
+                coverage::write_lcov(&coverage, &debug_info, &bytes, &file.display().to_string(), out)?;
+            }
+
+            let stdout = std::io::stdout();
+            coverage::write_listing(&coverage, &debug_info, &bytes, &source, stdout.lock())?;
+        },
+        Options::Dap => dap::serve()?,
+        Options::Fmt {
+            check,
+            files,
+        } => {
+            let mut unformatted = false;
+
+            for file in files {
+                let bytes = std::fs::read(&file)?;
+                let formatted = vm_assembler::format(&bytes)?;
+
+                if formatted.as_bytes() == bytes.as_slice() {
+                    continue;
+                }
+
+                if check {
+                    println!("{}", file.display());
+                    unformatted = true;
+                } else {
+                    std::fs::write(&file, formatted)?;
+                }
+            }
+
+            if unformatted {
+                std::process::exit(1);
+            }
+        },
+        Options::Lsp => lsp::serve()?,
+        Options::Profile {
+            memory_capacity,
+            max_steps,
+            symbols,
+            folded,
+            top,
+            file,
+        } => {
+            use std::cell::RefCell;
+            use std::rc::Rc;
+            use vm::prelude::*;
+
+            let bytes = std::fs::read(file)?;
+
+            let mut memory = Memory::with_capacity(memory_capacity);
+            memory.set_bytes(&bytes);
+
+            let symbols = match symbols {
+                Some(path) => profile::Symbols::parse(&std::fs::read_to_string(path)?)?,
+                None => profile::Symbols::default(),
+            };
+
+            let profiler = Rc::new(RefCell::new(Profiler::new()));
+
+            let mut cpu = Cpu::from(memory);
+            cpu.set_observer(Box::new(profiler.clone()));
+
+            match max_steps {
+                Some(max_steps) => cpu.run_for(max_steps),
+                None => cpu.run(),
+            };
+
+            let profiler = profiler.borrow();
+
+            let stderr = std::io::stderr();
+            profile::report(&profiler, &symbols, top, stderr.lock())?;
+
+            if let Some(path) = folded {
+                profile::write_folded(&profiler, &symbols, File::create(path)?)?;
+            }
+        },
         Options::Run {
             memory_capacity,
+            bank_image,
+            bank_size,
+            banked,
+            banks,
+            cycles,
+            display,
+            disk,
+            disk_image,
+            display_out,
+            dma,
+            frames,
+            gdb,
+            headless,
+            interrupt_handler,
+            keyboard,
+            max_steps,
+            rng,
+            rom,
+            rtc,
+            screen,
+            seed,
+            uart,
+            uart_backend,
+            virtual_clock,
+            load_state,
+            save_state,
             file
         } => {
+            use std::cell::RefCell;
+            use std::rc::Rc;
             use vm::prelude::*;
 
             let bytes = {
@@ -88,10 +529,212 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             };
 
             let mut memory = Memory::with_capacity(memory_capacity);
-            memory.set_bytes(&bytes);
+            let mut mm = MemoryMapper::new();
 
-            let mut cpu = Cpu::from(memory);
-            cpu.run();
+            if rom {
+                if bytes.is_empty() {
+                    return Err("the program is empty, so there's nothing to put in read-only memory".into());
+                }
+
+                let mut program = Memory::with_capacity(bytes.len());
+                program.set_bytes(&bytes);
+
+                mm.add_region(
+                    MemoryRegion::builder()
+                        .range(0..=bytes.len() - 1)
+                        .priority(1)
+                        .read_only(true)
+                        .memory(program)
+                        .finalize()
+                        .unwrap(),
+                )?;
+            } else {
+                memory.set_bytes(&bytes);
+            }
+
+            if let Some(addr) = keyboard {
+                let keyboard = Keyboard::stdin();
+                let len = keyboard.len();
+
+                map_device(&mut mm, addr, 1, Box::new(keyboard), len)?;
+            }
+
+            let framebuffer = Rc::new(RefCell::new(FramebufferScreen::new(16, 16)));
+
+            if let Some(addr) = screen {
+                let (device, len): (Box<dyn Device>, _) = if headless {
+                    (Box::new(framebuffer.clone()), framebuffer.borrow().len())
+                } else {
+                    let screen = ScreenDevice::new();
+                    let len = screen.len();
+
+                    (Box::new(screen), len)
+                };
+
+                map_device(&mut mm, addr, 1, device, len)?;
+            }
+
+            if let Some(addr) = uart {
+                let uart = uart_backend.open()?;
+                let len = uart.len();
+
+                map_device(&mut mm, addr, 1, Box::new(uart), len)?;
+            }
+
+            if let (Some(addr), Some(path)) = (disk, disk_image) {
+                let disk = BlockDevice::open(path)?;
+                let len = disk.len();
+
+                map_device(&mut mm, addr, 1, Box::new(disk), len)?;
+            }
+
+            if let Some(addr) = banked {
+                let mut memory = BankedMemory::new(banks, bank_size);
+
+                if let Some(path) = bank_image {
+                    let image = std::fs::read(path)?;
+                    if image.len() > banks * bank_size {
+                        return Err(format!(
+                            "bank image is {:#x?} bytes, more than the {:#x?} the banks hold",
+                            image.len(),
+                            banks * bank_size,
+                        ).into());
+                    }
+
+                    for (bank, bytes) in memory.banks_mut().iter_mut().zip(image.chunks(bank_size)) {
+                        bank.set_bytes(bytes);
+                    }
+                }
+
+                let len = memory.len();
+
+                map_device(&mut mm, addr, 1, Box::new(memory), len)?;
+            }
+
+            if let Some(addr) = dma {
+                let dma = Dma::new();
+                let len = dma.len();
+
+                map_device(&mut mm, addr, 1, Box::new(dma), len)?;
+            }
+
+            if let Some(addr) = rng {
+                let rng = match seed {
+                    Some(seed) => Rng::new(seed),
+                    None => Rng::from_time(),
+                };
+
+                let len = rng.len();
+
+                map_device(&mut mm, addr, 1, Box::new(rng), len)?;
+            }
+
+            if let Some(addr) = rtc {
+                let rtc = match virtual_clock {
+                    Some(hz) => Rtc::virtual_clock(0, hz),
+                    None => Rtc::host(),
+                };
+
+                let len = rtc.len();
+
+                map_device(&mut mm, addr, 1, Box::new(rtc), len)?;
+            }
+
+            let pixels = Rc::new(RefCell::new(PixelDisplay::default()));
+
+            if let Some(addr) = display {
+                let len = pixels.borrow().len();
+
+                map_device(&mut mm, addr, 1, Box::new(pixels.clone()), len)?;
+            }
+
+            mm.add_region(
+                MemoryRegion::builder()
+                    .range(memory.get_range())
+                    .memory(memory)
+                    .finalize()
+                    .unwrap(),
+            )?;
+
+            let mut cpu = Cpu::from(mm);
+            cpu.set_interrupt_handler(interrupt_handler);
+
+            if let Some(path) = load_state {
+                let snapshot = Snapshot::read_from(File::open(path)?)?;
+                cpu.restore(&snapshot)?;
+            }
+
+            let session = match gdb {
+                Some(addr) => {
+                    let server = vm::gdb::GdbServer::bind(addr)?;
+                    eprintln!("waiting for gdb on {}", server.local_addr()?);
+                    server.serve(&mut cpu)?
+                },
+                None => SessionEnd::Detached,
+            };
+
+            if session == SessionEnd::Killed {
+                // puts the terminal back the way it was before exiting
+                drop(cpu);
+
+                eprintln!("killed by gdb");
+                std::process::exit(1);
+            }
+
+            let reason = match (session, max_steps) {
+                (SessionEnd::Halted, _) => StopReason::Halt,
+                (_, Some(max_steps)) => cpu.run_for(max_steps),
+                (_, None) => cpu.run(),
+            };
+
+            if let Some(path) = save_state {
+                cpu.snapshot().write_to(File::create(path)?)?;
+            }
+
+            if cycles {
+                eprintln!("{} cycles", cpu.cycles());
+            }
+
+            if headless && screen.is_some() {
+                print!("{}", framebuffer.borrow().render_text());
+            }
+
+            if display.is_some() {
+                let pixels = pixels.borrow();
+
+                if let Some(path) = display_out {
+                    let out = std::io::BufWriter::new(File::create(&path)?);
+
+                    match path.extension().and_then(|ext| ext.to_str()) {
+                        Some("ppm") => pixels.write_ppm(out)?,
+                        _ => pixels.write_png(out)?,
+                    }
+                }
+
+                if let Some(dir) = frames {
+                    std::fs::create_dir_all(&dir)?;
+
+                    for (i, frame) in pixels.frames().iter().enumerate() {
+                        let out = File::create(dir.join(format!("frame-{:04}.png", i)))?;
+                        write_png(pixels.width(), pixels.height(), frame, std::io::BufWriter::new(out))?;
+                    }
+                }
+            }
+
+            // puts the terminal back the way it was before exiting
+            drop(cpu);
+
+            match reason {
+                StopReason::BudgetExhausted => {
+                    eprintln!("stopped after {} instructions without halting", max_steps.unwrap_or_default());
+                    std::process::exit(2);
+                },
+                StopReason::Fault(fault) => {
+                    eprintln!("fault: {}", fault);
+                    std::process::exit(3);
+                },
+                _ => (),
+            }
         }
     }
 
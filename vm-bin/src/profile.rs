@@ -0,0 +1,147 @@
+//! `vm-bin profile`: runs a program under a `Profiler` and reports where the
+//! instructions went.
+//!
+//! Symbol files, as written by `vm-bin assemble --symbols`, have one label
+//! per line: its address in hex, a space, and its name.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use vm::prelude::*;
+
+#[derive(Debug, Default)]
+pub struct Symbols(BTreeMap<Addr, String>);
+
+impl Symbols {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut symbols = BTreeMap::new();
+
+        for (i, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (addr, name) = line.split_once(' ')
+                .ok_or_else(|| format!("line {}: expected `ADDR NAME`", i + 1))?;
+
+            let addr = Addr::from_str_radix(addr.trim_start_matches("0x"), 16)
+                .map_err(|err| format!("line {}: {}", i + 1, err))?;
+
+            symbols.insert(addr, name.trim().to_string());
+        }
+
+        Ok(Self(symbols))
+    }
+
+    pub fn write_to<W: Write>(labels: &[(String, Addr)], mut out: W) -> io::Result<()> {
+        for (label, addr) in labels {
+            writeln!(out, "{:04x} {}", addr, label)?;
+        }
+
+        Ok(())
+    }
+
+    /// `label`, `label+0x3` for addresses past the closest label, or the bare
+    /// address.
+    pub fn name(&self, addr: Addr) -> String {
+        match self.0.range(..=addr).next_back() {
+            Some((label_addr, label)) if *label_addr == addr => label.clone(),
+            Some((label_addr, label)) => format!("{}+{:#x}", label, addr - label_addr),
+            None => format!("{:#06x}", addr),
+        }
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+pub fn report<W: Write>(profiler: &Profiler, symbols: &Symbols, top: usize, mut out: W) -> io::Result<()> {
+    let total = profiler.total();
+    writeln!(out, "{} instructions", total)?;
+
+    let mut by_addr: Vec<(Addr, u64)> = profiler.by_addr().iter()
+        .map(|(addr, count)| (*addr, *count))
+        .collect();
+    by_addr.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    writeln!(out)?;
+    writeln!(out, "hot spots:")?;
+    for (addr, count) in by_addr.into_iter().take(top) {
+        writeln!(out, "  {:#06x} {:<24} {:>10} {:>6.2}%", addr, symbols.name(addr), count, percent(count, total))?;
+    }
+
+    let mut by_opcode: Vec<(Byte, u64)> = profiler.by_opcode().iter()
+        .map(|(opcode, count)| (*opcode, *count))
+        .collect();
+    by_opcode.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    writeln!(out)?;
+    writeln!(out, "by opcode:")?;
+    for (opcode, count) in by_opcode {
+        let variant = InstructionVariant::from(opcode);
+        let name = format!("{} ({:?})", variant.as_str(), variant);
+
+        writeln!(out, "  {:#04x} {:<24} {:>10} {:>6.2}%", opcode, name, count, percent(count, total))?;
+    }
+
+    let mut subroutines: Vec<(Addr, SubroutineProfile)> = profiler.subroutines().iter()
+        .map(|(addr, profile)| (*addr, *profile))
+        .collect();
+    subroutines.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(&b.0)));
+
+    writeln!(out)?;
+    writeln!(out, "by subroutine:")?;
+    writeln!(out, "  {:<31} {:>10} {:>10} {:>10}", "", "calls", "inclusive", "exclusive")?;
+    for (addr, profile) in subroutines {
+        writeln!(
+            out,
+            "  {:#06x} {:<24} {:>10} {:>10} {:>10}",
+            addr,
+            symbols.name(addr),
+            profile.calls,
+            profile.inclusive,
+            profile.exclusive,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Folded stacks, one `outer;inner count` line per call stack, as read by
+/// `flamegraph.pl` and compatible tools.
+pub fn write_folded<W: Write>(profiler: &Profiler, symbols: &Symbols, mut out: W) -> io::Result<()> {
+    for (stack, count) in profiler.stacks() {
+        let names: Vec<String> = stack.iter()
+            .map(|addr| symbols.name(*addr))
+            .collect();
+
+        writeln!(out, "{} {}", names.join(";"), count)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_name_addresses() {
+        let mut out = Vec::new();
+        Symbols::write_to(&[ ("start".into(), 0x0000), ("loop".into(), 0x0005) ], &mut out).unwrap();
+
+        let symbols = Symbols::parse(std::str::from_utf8(&out).unwrap()).unwrap();
+
+        assert_eq!(symbols.name(0x0000), "start");
+        assert_eq!(symbols.name(0x0005), "loop");
+        assert_eq!(symbols.name(0x0008), "loop+0x3");
+        assert_eq!(Symbols::default().name(0x0008), "0x0008");
+
+        assert!(Symbols::parse("0005").is_err());
+    }
+}
@@ -0,0 +1,43 @@
+//! The `Content-Length` framed JSON messages shared by the Debug Adapter
+//! Protocol and the Language Server Protocol.
+
+use serde_json::Value;
+use std::io::{self, BufRead, Write};
+
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some(len) = header.strip_prefix("Content-Length:") {
+            content_length = len.trim().parse().ok();
+        }
+    }
+
+    let content_length = content_length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing `Content-Length` header")
+    })?;
+
+    let mut content = vec![0; content_length];
+    reader.read_exact(&mut content)?;
+
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message<W: Write>(out: &mut W, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+
+    write!(out, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    out.flush()
+}
@@ -0,0 +1,134 @@
+//! `vm-bin run --uart-backend`: what the UART is connected to.
+//!
+//! - `stdio`: stdin and stdout
+//! - `file:IN,OUT`: reads from `IN` and writes to `OUT`, either of which may
+//!   be left out; named pipes work the same way, though opening one waits
+//!   for the other end
+//! - `tcp:HOST:PORT`: a TCP connection
+//! - `unix:PATH`: a Unix socket connection
+
+use std::fs::File;
+use std::io;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use vm::prelude::*;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Backend {
+    Stdio,
+    File {
+        input: Option<PathBuf>,
+        output: Option<PathBuf>,
+    },
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = spec.split_once(':').unwrap_or((spec, ""));
+
+        let path = |path: &str| match path {
+            "" => None,
+            path => Some(PathBuf::from(path)),
+        };
+
+        match kind {
+            "stdio" if arg.is_empty() => Ok(Self::Stdio),
+            "file" => {
+                let (input, output) = arg.split_once(',').unwrap_or((arg, ""));
+                Ok(Self::File {
+                    input: path(input),
+                    output: path(output),
+                })
+            },
+            "tcp" if !arg.is_empty() => Ok(Self::Tcp(arg.to_string())),
+            "unix" if !arg.is_empty() => Ok(Self::Unix(PathBuf::from(arg))),
+            _ => Err(format!(
+                "expected `stdio`, `file:IN,OUT`, `tcp:HOST:PORT` or `unix:PATH`, found `{}`",
+                spec,
+            )),
+        }
+    }
+}
+
+impl Backend {
+    pub fn open(&self) -> io::Result<Uart> {
+        match self {
+            Self::Stdio => Ok(Uart::stdio()),
+            Self::File { input, output } => {
+                let input = input.as_ref().map(File::open).transpose()?;
+
+                match output {
+                    Some(output) => Ok(Uart::from_io(input, File::create(output)?)),
+                    None => Ok(Uart::from_io(input, io::sink())),
+                }
+            },
+            Self::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                Ok(Uart::from_io(Some(stream.try_clone()?), stream))
+            },
+            #[cfg(unix)]
+            Self::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                Ok(Uart::from_io(Some(stream.try_clone()?), stream))
+            },
+            #[cfg(not(unix))]
+            Self::Unix(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix sockets aren't supported here",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read as _, Write as _};
+    use std::net::TcpListener;
+
+    #[test]
+    fn can_parse() {
+        assert_eq!("stdio".parse(), Ok(Backend::Stdio));
+        assert_eq!("file:in.txt".parse(), Ok(Backend::File {
+            input: Some("in.txt".into()),
+            output: None,
+        }));
+        assert_eq!("file:,out.txt".parse(), Ok(Backend::File {
+            input: None,
+            output: Some("out.txt".into()),
+        }));
+        assert_eq!("tcp:localhost:4000".parse(), Ok(Backend::Tcp("localhost:4000".into())));
+        assert_eq!("unix:/tmp/vm.sock".parse(), Ok(Backend::Unix("/tmp/vm.sock".into())));
+
+        assert!("tcp".parse::<Backend>().is_err());
+        assert!("serial:/dev/ttyS0".parse::<Backend>().is_err());
+    }
+
+    #[test]
+    fn can_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let backend = Backend::Tcp(listener.local_addr().unwrap().to_string());
+
+        let mut uart = backend.open().unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+
+        uart.set_u16(Uart::TX, b'a' as Short);
+
+        let mut buf = [0];
+        peer.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"a");
+
+        peer.write_all(b"b").unwrap();
+        while uart.queue().is_empty() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            uart.tick(1);
+        }
+
+        assert_eq!(uart.get_u16(Uart::RX), b'b' as Short);
+    }
+}
@@ -1,23 +1,152 @@
 use criterion::{black_box, criterion_group, criterion_main};
+use vm::instructions::constants::*;
 use vm::prelude::*;
+use vm::registers::constants::{ACC, R1, R2};
 
-fn run<'a>(bytes: &'a [Byte]) {
+fn run(bytes: &[Byte], decode_cache: bool) {
     let mut memory = Memory::with_capacity(0x10000);
     memory.set_bytes(bytes);
 
     let mut cpu = Cpu::from(memory);
+    cpu.set_decode_cache(decode_cache);
     cpu.run();
 }
 
+/// Counts `acc` down from `0x2000`, so the same few instructions run many
+/// times over.
+fn countdown() -> Vec<Byte> {
+    vec![
+        // move lit (0x2000) reg (acc)
+        MOV_LIT_REG, 0x20, 0x00, ACC,
+
+        // loop: dec reg (acc)
+        DEC_REG, ACC,
+
+        // round trip through memory, to have something to do
+        MOV_REG_MEM, ACC, 0x10, 0x00,
+        MOV_MEM_REG, 0x10, 0x00, ACC,
+
+        // jne lit (0x0000) mem (loop)
+        JNE_LIT, 0x00, 0x00, 0x00, 0x04,
+
+        HLT,
+    ]
+}
+
+/// Like `countdown`, but everything stays in registers.
+fn register_countdown() -> Vec<Byte> {
+    vec![
+        // move lit (0x2000) reg (acc)
+        MOV_LIT_REG, 0x20, 0x00, ACC,
+
+        // loop: dec reg (acc)
+        DEC_REG, ACC,
+
+        MOV_REG_REG, ACC, R1,
+        INC_REG, R2,
+        XOR_REG_REG, R1, R2,
+        MOV_REG_REG, R1, ACC,
+
+        // jne lit (0x0000) mem (loop)
+        JNE_LIT, 0x00, 0x00, 0x00, 0x04,
+
+        HLT,
+    ]
+}
+
 fn criterion_benchmark(c: &mut criterion::Criterion) {
     c.bench_function("binary 1", |b| b.iter(|| {
-        run(black_box(include_bytes!("../tests/binary1")))
+        run(black_box(include_bytes!("../tests/binary1")), true)
+    }));
+
+    c.bench_function("binary 1 without decode cache", |b| b.iter(|| {
+        run(black_box(include_bytes!("../tests/binary1")), false)
     }));
 
     c.bench_function("binary 2", |b| b.iter(|| {
-        run(black_box(include_bytes!("../tests/binary2")))
+        run(black_box(include_bytes!("../tests/binary2")), true)
+    }));
+
+    let countdown = countdown();
+
+    c.bench_function("countdown", |b| b.iter(|| {
+        run(black_box(&countdown), true)
+    }));
+
+    c.bench_function("countdown without decode cache", |b| b.iter(|| {
+        run(black_box(&countdown), false)
+    }));
+}
+
+fn registers_benchmark(c: &mut criterion::Criterion) {
+    let countdown = register_countdown();
+
+    c.bench_function("register countdown", |b| b.iter(|| {
+        run(black_box(&countdown), true)
+    }));
+
+    c.bench_function("register reads", |b| {
+        let cpu = Cpu::from(Memory::with_capacity(0x100));
+
+        b.iter(|| {
+            RegisterVariant::VARIANTS.iter()
+                .fold(0u16, |sum, reg| sum.wrapping_add(cpu.register(black_box(*reg))))
+        })
+    });
+}
+
+/// Builds a `Cpu` with a few devices mapped over RAM, then runs a short
+/// program, so setting up the memory map counts as much as running.
+fn mapped(bytes: &[Byte]) {
+    let mut memory = Memory::with_capacity(0x10000);
+    memory.set_bytes(bytes);
+
+    let mut mm = MemoryMapper::new();
+
+    mm.add_region(
+        MemoryRegion::builder()
+            .range(0x3000..=0x30ff)
+            .priority(1)
+            .device(Box::new(FramebufferScreen::new(16, 16)))
+            .finalize()
+            .unwrap(),
+    ).unwrap();
+
+    mm.add_region(
+        MemoryRegion::builder()
+            .range(0x3180..=0x3183)
+            .priority(1)
+            .device(Box::new(Rng::new(0)))
+            .finalize()
+            .unwrap(),
+    ).unwrap();
+
+    mm.add_region(
+        MemoryRegion::builder()
+            .range(0x4000..=0x4009)
+            .priority(1)
+            .device(Box::new(Dma::new()))
+            .finalize()
+            .unwrap(),
+    ).unwrap();
+
+    mm.add_region(
+        MemoryRegion::builder()
+            .range(0x0000..=0xffff)
+            .memory(memory)
+            .finalize()
+            .unwrap(),
+    ).unwrap();
+
+    let mut cpu = Cpu::from(mm);
+    cpu.run();
+}
+
+fn mapper_benchmark(c: &mut criterion::Criterion) {
+    c.bench_function("binary 1 with devices mapped", |b| b.iter(|| {
+        mapped(black_box(include_bytes!("../tests/binary1")))
     }));
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(benches, criterion_benchmark, registers_benchmark, mapper_benchmark);
 criterion_main!(benches);
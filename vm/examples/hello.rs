@@ -64,18 +64,19 @@ fn main() {
     mm.add_region(
         MemoryRegion::builder()
             .range(0x3000..=0x30ff)
+            .priority(1)
             .device(Box::new(screen))
             .finalize()
             .unwrap(),
-    );
+    ).unwrap();
 
     mm.add_region(
         MemoryRegion::builder()
             .range(memory.get_range())
-            .device(Box::new(memory))
+            .memory(memory)
             .finalize()
             .unwrap(),
-    );
+    ).unwrap();
 
     let mut cpu = Cpu::from(mm);
     cpu.run();
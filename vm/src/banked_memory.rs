@@ -0,0 +1,241 @@
+use crate::prelude::*;
+
+/// Several banks of RAM sharing one window of the address space, for more
+/// memory than a 16-bit address reaches. The window shows one bank at a
+/// time; after it comes the bank register, which selects the bank by
+/// number. Switching banks changes what the window holds without writing to
+/// it, so instructions in the window are never cached.
+#[derive(Clone, Debug)]
+pub struct BankedMemory {
+    banks: Vec<Memory>,
+    fault: Option<DeviceFault>,
+    selected: usize,
+    size: usize,
+}
+
+impl BankedMemory {
+    /// `count` banks of `size` bytes each, all zeroed, with bank 0 selected.
+    pub fn new(count: usize, size: usize) -> Self {
+        Self {
+            banks: (0..count.max(1)).map(|_| Memory(vec![0; size])).collect(),
+            fault: None,
+            selected: 0,
+            size,
+        }
+    }
+
+    /// How many bytes of the window each bank fills.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Where the bank register is, relative to the start of the window.
+    /// It's always even, so there's a spare byte after an odd size.
+    pub fn bank_addr(&self) -> Addr {
+        ((self.size + 1) & !1) as Addr
+    }
+
+    /// How many addresses the window and the bank register take up.
+    pub fn len(&self) -> usize {
+        self.bank_addr() as usize + 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn banks(&self) -> &[Memory] {
+        &self.banks
+    }
+
+    /// For loading banks before the program runs.
+    pub fn banks_mut(&mut self) -> &mut [Memory] {
+        &mut self.banks
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    fn select(&mut self, bank: Short) {
+        if (bank as usize) < self.banks.len() {
+            self.selected = bank as usize;
+            return;
+        }
+
+        self.fault = Some(DeviceFault::new(format!(
+            "no bank {:#06x?}, there are only {}",
+            bank,
+            self.banks.len(),
+        )));
+    }
+}
+
+impl Read for BankedMemory {
+    fn get_u8(&self, addr: Addr) -> Byte {
+        if (addr as usize) < self.size {
+            return self.banks[self.selected].get_u8(addr);
+        }
+
+        self.get_register_u8(addr)
+    }
+
+    fn get_u16(&self, addr: Addr) -> Short {
+        if addr == self.bank_addr() {
+            return self.selected as Short;
+        }
+
+        if (addr as usize) < self.size {
+            return ((self.get_u8(addr) as Short) << 8) | self.get_u8(addr + 1) as Short;
+        }
+
+        0
+    }
+}
+
+impl Write for BankedMemory {
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        if (addr as usize) < self.size {
+            self.banks[self.selected].set_u8(addr, val);
+            return;
+        }
+
+        self.set_register_u8(addr, val);
+    }
+
+    fn set_u16(&mut self, addr: Addr, val: Short) {
+        if addr == self.bank_addr() {
+            self.select(val);
+        } else if (addr as usize) < self.size {
+            self.set_u8(addr, (val >> 8) as Byte);
+            self.set_u8(addr + 1, val as Byte);
+        }
+    }
+}
+
+impl WordRegisters for BankedMemory {}
+
+impl Device for BankedMemory {
+    // the window's contents change without it being written to
+    fn cacheable(&self) -> bool {
+        false
+    }
+
+    fn save_state(&self) -> Vec<Byte> {
+        let mut state = (self.selected as Short).to_be_bytes().to_vec();
+
+        for bank in self.banks.iter() {
+            state.extend_from_slice(&bank.0);
+        }
+
+        state
+    }
+
+    fn load_state(&mut self, state: &[Byte]) -> Result<(), SnapshotError> {
+        let expected = 2 + self.banks.len() * self.size;
+
+        if state.len() != expected {
+            return Err(SnapshotError::new(format!(
+                "expected {:#x?} bytes of banked memory, found {:#x?}",
+                expected,
+                state.len(),
+            )));
+        }
+
+        self.selected = (Short::from_be_bytes([state[0], state[1]]) as usize).min(self.banks.len() - 1);
+
+        for (bank, bytes) in self.banks.iter_mut().zip(state[2..].chunks(self.size.max(1))) {
+            bank.0.copy_from_slice(bytes);
+        }
+
+        Ok(())
+    }
+
+    fn take_fault(&mut self) -> Option<DeviceFault> {
+        self.fault.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+
+    #[test]
+    fn can_switch_banks() {
+        let mut banked = BankedMemory::new(2, 0x10);
+        assert_eq!(banked.len(), 0x12);
+
+        banked.set_u16(0x0000, 0xaaaa);
+        banked.set_u16(banked.bank_addr(), 1);
+        assert_eq!(banked.get_u16(0x0000), 0x0000);
+
+        banked.set_u16(0x000e, 0xbbbb);
+        banked.set_u8(banked.bank_addr() + 1, 0);
+        assert_eq!(banked.get_u16(banked.bank_addr()), 0);
+        assert_eq!(banked.get_u16(0x0000), 0xaaaa);
+        assert_eq!(banked.get_u16(0x000e), 0x0000);
+
+        // a bank that isn't there leaves the selection alone
+        banked.set_u16(banked.bank_addr(), 2);
+        assert_eq!(banked.take_fault(), Some(DeviceFault::new("no bank 0x0002, there are only 2")));
+        assert_eq!(banked.selected(), 0);
+
+        let mut copy = BankedMemory::new(2, 0x10);
+        copy.load_state(&banked.save_state()).unwrap();
+        assert_eq!(copy.banks()[1].get_u16(0x000e), 0xbbbb);
+    }
+
+    #[test]
+    fn can_run_code_from_banks() {
+        let mut memory = Memory::with_capacity(0x10000);
+
+        // jump if equal (0x0000, acc) (0x8000), into the window; once back,
+        // move lit (0x0001) mem (bank register) and jump there again
+        memory.set_bytes(&[
+            JEQ_LIT, 0x00, 0x00, 0x80, 0x00,
+            MOV_LIT_MEM, 0x00, 0x01, 0x81, 0x00,
+            JEQ_LIT, 0x00, 0x00, 0x80, 0x00,
+        ]);
+
+        // move lit (0x1111) mem (0x2000), jump back
+        let mut banked = BankedMemory::new(2, 0x100);
+        banked.banks_mut()[0].set_bytes(&[
+            MOV_LIT_MEM, 0x11, 0x11, 0x20, 0x00,
+            JEQ_LIT, 0x00, 0x00, 0x00, 0x05,
+        ]);
+
+        // the same addresses, now holding move lit (0x2222) mem (0x2002),
+        // halt
+        banked.banks_mut()[1].set_bytes(&[
+            MOV_LIT_MEM, 0x22, 0x22, 0x20, 0x02,
+            HLT,
+        ]);
+
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x8000..=0x8000 + banked.len() - 1)
+                .priority(1)
+                .device(Box::new(banked))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(memory.get_range())
+                .memory(memory)
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        let mut cpu = Cpu::from(mm);
+
+        // stale decoded instructions would loop back to bank 0 forever
+        assert_eq!(cpu.run_for(10), StopReason::Halt);
+        assert_eq!(cpu.get_u16(0x2000), 0x1111);
+        assert_eq!(cpu.get_u16(0x2002), 0x2222);
+    }
+}
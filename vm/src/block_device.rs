@@ -0,0 +1,391 @@
+use crate::prelude::*;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom};
+use std::path::Path;
+
+pub const SECTOR_SIZE: usize = 512;
+
+const BUSY: Short = 0b001;
+const ERROR: Short = 0b010;
+const INTERRUPTS: Short = 0b100;
+
+const READ: Short = 0x0001;
+const WRITE: Short = 0x0002;
+
+/// A disk controller for a disk image, ten bytes wide. The program picks a
+/// sector and a buffer in memory, then writes a command; the controller
+/// copies the whole sector between the image and the buffer over the bus
+/// after the instruction that wrote the command, and can interrupt once it's
+/// done. Sectors are 512 bytes.
+#[derive(Debug)]
+pub struct BlockDevice<S = File> {
+    buffer: Addr,
+    command: Short,
+    fault: Option<DeviceFault>,
+    interrupts: bool,
+    pending: bool,
+    sector: Short,
+    sectors: Short,
+    status: Short,
+    storage: S,
+}
+
+impl BlockDevice {
+    /// Opens the disk image at `path` for reading and writing. A last
+    /// partial sector reads as if it was padded with zeroes.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Self::new(file)
+    }
+}
+
+impl<S> BlockDevice<S> {
+    /// The sector to read or write.
+    pub const SECTOR: Addr = 0x0000;
+
+    /// Where in memory the sector is copied to or from.
+    pub const BUFFER: Addr = 0x0002;
+
+    /// Writing `0x0001` reads the sector into the buffer and `0x0002` writes
+    /// the buffer to the sector. Reads back the last command.
+    pub const COMMAND: Addr = 0x0004;
+
+    /// Bit 0: a command is waiting to run. Bit 1: the last command failed,
+    /// e.g. because the sector is past the end of the image. Bit 2:
+    /// interrupts when a command finishes are enabled; write the bit to
+    /// change it.
+    pub const STATUS: Addr = 0x0006;
+
+    /// How many sectors the image has. Read-only.
+    pub const SECTORS: Addr = 0x0008;
+
+    /// How many addresses the device takes up.
+    pub fn len(&self) -> usize {
+        Self::SECTORS as usize + 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+}
+
+impl<S: io::Read + io::Write + Seek> BlockDevice<S> {
+    pub fn new(mut storage: S) -> io::Result<Self> {
+        let len = storage.seek(SeekFrom::End(0))? as usize;
+        // sectors past what the register can count can't be reached
+        let sectors = len.div_ceil(SECTOR_SIZE).min(Short::MAX as usize) as Short;
+
+        Ok(Self {
+            buffer: 0,
+            command: 0,
+            fault: None,
+            interrupts: false,
+            pending: false,
+            sector: 0,
+            sectors,
+            status: 0,
+            storage,
+        })
+    }
+
+    fn read_sector(&mut self) -> io::Result<[Byte; SECTOR_SIZE]> {
+        let mut data = [0; SECTOR_SIZE];
+
+        self.storage.seek(SeekFrom::Start((self.sector as usize * SECTOR_SIZE) as u64))?;
+
+        let mut read = 0;
+        while read < SECTOR_SIZE {
+            match self.storage.read(&mut data[read..])? {
+                0 => break,
+                len => read += len,
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn write_sector(&mut self, data: &[Byte]) -> io::Result<()> {
+        self.storage.seek(SeekFrom::Start((self.sector as usize * SECTOR_SIZE) as u64))?;
+        self.storage.write_all(data)?;
+        self.storage.flush()
+    }
+
+    /// Runs the waiting command. `Err` means the program has to be stopped;
+    /// problems with the image only set the error bit.
+    fn run(&mut self, bus: &mut Bus<'_>) -> Result<(), DeviceFault> {
+        if self.sector >= self.sectors {
+            self.status |= ERROR;
+            return Ok(());
+        }
+
+        let start = self.buffer;
+        let buffer = |i: usize| start.wrapping_add(i as Addr);
+
+        match self.command {
+            READ => {
+                let data = match self.read_sector() {
+                    Ok(data) => data,
+                    Err(_) => {
+                        self.status |= ERROR;
+                        return Ok(());
+                    },
+                };
+
+                for (i, byte) in data.iter().enumerate() {
+                    bus.set_u8(buffer(i), *byte)?;
+                }
+            },
+            WRITE => {
+                let mut data = [0; SECTOR_SIZE];
+                for (i, byte) in data.iter_mut().enumerate() {
+                    *byte = bus.get_u8(buffer(i))?;
+                }
+
+                if self.write_sector(&data).is_err() {
+                    self.status |= ERROR;
+                }
+            },
+            command => {
+                return Err(DeviceFault::new(format!("unknown disk command {:#06x?}", command)));
+            },
+        }
+
+        Ok(())
+    }
+}
+
+impl<S> Read for BlockDevice<S> {
+    fn get_u8(&self, addr: Addr) -> Byte {
+        self.get_register_u8(addr)
+    }
+
+    fn get_u16(&self, addr: Addr) -> Short {
+        match addr {
+            Self::SECTOR => self.sector,
+            Self::BUFFER => self.buffer,
+            Self::COMMAND => self.command,
+            Self::STATUS => self.status | if self.interrupts { INTERRUPTS } else { 0 },
+            Self::SECTORS => self.sectors,
+            _ => 0,
+        }
+    }
+}
+
+impl<S> Write for BlockDevice<S> {
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        self.set_register_u8(addr, val);
+    }
+
+    fn set_u16(&mut self, addr: Addr, val: Short) {
+        match addr {
+            Self::SECTOR => self.sector = val,
+            Self::BUFFER => self.buffer = val,
+            Self::COMMAND => {
+                self.command = val;
+                self.status = BUSY;
+            },
+            Self::STATUS => self.interrupts = val & INTERRUPTS != 0,
+            _ => (),
+        }
+    }
+}
+
+impl<S> WordRegisters for BlockDevice<S> {}
+
+impl<S: io::Read + io::Write + Seek + std::fmt::Debug> Device for BlockDevice<S> {
+    fn take_interrupt(&mut self) -> bool {
+        std::mem::replace(&mut self.pending, false)
+    }
+
+    fn take_fault(&mut self) -> Option<DeviceFault> {
+        self.fault.take()
+    }
+
+    fn access_bus(&mut self, bus: &mut Bus<'_>) {
+        if self.status & BUSY == 0 {
+            return;
+        }
+
+        self.status &= !BUSY;
+
+        if let Err(fault) = self.run(bus) {
+            self.fault = Some(fault);
+        }
+
+        self.pending |= self.interrupts;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    type Disk = Rc<RefCell<BlockDevice<Cursor<Vec<Byte>>>>>;
+
+    fn cpu(image: Vec<Byte>, program: &[Byte]) -> (Cpu, Disk) {
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(program);
+
+        let disk = Rc::new(RefCell::new(BlockDevice::new(Cursor::new(image)).unwrap()));
+
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x3000..=0x3009)
+                .priority(1)
+                .device(Box::new(disk.clone()))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(memory.get_range())
+                .memory(memory)
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        (Cpu::from(mm), disk)
+    }
+
+    /// move lit (val) mem (addr)
+    fn store(addr: Addr, val: Short) -> Vec<Byte> {
+        let [hi, lo] = val.to_be_bytes();
+        let [addr_hi, addr_lo] = addr.to_be_bytes();
+
+        vec![MOV_LIT_MEM, hi, lo, addr_hi, addr_lo]
+    }
+
+    #[test]
+    fn can_read_sector() {
+        let mut image = vec![0; SECTOR_SIZE];
+        image.extend((0..SECTOR_SIZE).map(|i| i as Byte));
+
+        let program = [
+            store(0x3000, 0x0001),
+            store(0x3002, 0x1000),
+            store(0x3004, READ),
+            vec![HLT],
+        ].concat();
+
+        let (mut cpu, disk) = cpu(image, &program);
+        assert_eq!(disk.borrow().get_u16(0x0008), 2);
+
+        let before = cpu.cycles();
+        assert_eq!(cpu.run(), StopReason::Halt);
+
+        assert_eq!(cpu.get_u8(0x1000), 0x00);
+        assert_eq!(cpu.get_u8(0x10ff), 0xff);
+        assert_eq!(cpu.get_u8(0x11ff), 0xff);
+        assert_eq!(cpu.get_u8(0x1200), 0x00);
+        assert_eq!(disk.borrow().get_u16(0x0006), 0);
+
+        // three moves, a halt, and every byte copied
+        assert!(cpu.cycles() - before > SECTOR_SIZE as u64);
+    }
+
+    #[test]
+    fn can_write_sector() {
+        let program = [
+            store(0x1000, 0xabcd),
+            store(0x3000, 0x0000),
+            store(0x3002, 0x1000),
+            store(0x3006, INTERRUPTS),
+            store(0x3004, WRITE),
+            vec![HLT],
+        ].concat();
+
+        let (mut cpu, disk) = cpu(vec![0xff; 600], &program);
+        assert_eq!(cpu.run(), StopReason::Halt);
+
+        let disk = disk.borrow();
+        let image = disk.storage().get_ref();
+
+        assert_eq!(image.len(), 600);
+        assert_eq!(&image[..3], &[0xab, 0xcd, 0x00]);
+        assert_eq!(image[SECTOR_SIZE - 1], 0x00);
+        assert_eq!(image[SECTOR_SIZE], 0xff);
+    }
+
+    #[test]
+    fn can_watch_and_step_back_sector_reads() {
+        let mut image = vec![0; SECTOR_SIZE];
+        image.extend((0..SECTOR_SIZE).map(|i| i as Byte));
+
+        let program = [
+            store(0x3000, 0x0001),
+            store(0x3002, 0x1000),
+            store(0x3004, READ),
+            vec![HLT],
+        ].concat();
+
+        let (mut cpu, _) = cpu(image, &program);
+        cpu.enable_history(0x100);
+        cpu.debugger_mut().add_watchpoint(0x1010..=0x1010, WatchKind::Write);
+
+        assert_eq!(cpu.run(), StopReason::Watch {
+            addr: 0x1010,
+            kind: WatchKind::Write,
+            old: Some(0x00),
+            new: 0x10,
+        });
+        assert_eq!(cpu.run(), StopReason::Halt);
+        assert_eq!(cpu.get_u8(0x11ff), 0xff);
+
+        // the sector goes back to what was in RAM before
+        assert!(cpu.run_back_to(0x0000));
+        assert_eq!(cpu.get_u8(0x1010), 0x00);
+        assert_eq!(cpu.get_u8(0x11ff), 0x00);
+    }
+
+    #[test]
+    fn can_fail() {
+        let program = [
+            store(0x3000, 0x0002),
+            store(0x3004, READ),
+            store(0x3000, 0x0000),
+            store(0x3004, 0x0003),
+            vec![HLT],
+        ].concat();
+
+        let (mut cpu, disk) = cpu(vec![0; 2 * SECTOR_SIZE], &program);
+
+        // the sector past the end only sets the error bit
+        assert_eq!(cpu.run_for(2), StopReason::BudgetExhausted);
+        assert_eq!(disk.borrow().get_u16(0x0006), ERROR);
+
+        assert_eq!(cpu.run(), StopReason::Fault(DeviceFault::new("unknown disk command 0x0003")));
+        assert_eq!(disk.borrow().get_u16(0x0006), 0);
+    }
+
+    #[test]
+    fn can_open_large_image() {
+        let path = std::env::temp_dir().join(format!("block-device-{}.img", std::process::id()));
+
+        // sparse, so it takes up next to no space
+        let file = File::create(&path).unwrap();
+        file.set_len(0x10001 * SECTOR_SIZE as u64).unwrap();
+
+        let mut disk = BlockDevice::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(disk.get_u16(BlockDevice::<File>::SECTORS), 0xffff);
+
+        disk.set_u16(BlockDevice::<File>::SECTOR, 0xfffe);
+        assert_eq!(disk.read_sector().unwrap(), [0; SECTOR_SIZE]);
+    }
+}
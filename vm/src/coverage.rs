@@ -0,0 +1,94 @@
+use crate::prelude::*;
+use std::collections::BTreeMap;
+
+/// How often a conditional jump went each way.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BranchCoverage {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Which instructions ran, keyed by the address they start at, and which way
+/// every conditional jump went.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Coverage {
+    executed: BTreeMap<Addr, u64>,
+    branches: BTreeMap<Addr, BranchCoverage>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn executed(&self) -> &BTreeMap<Addr, u64> {
+        &self.executed
+    }
+
+    pub fn branches(&self) -> &BTreeMap<Addr, BranchCoverage> {
+        &self.branches
+    }
+
+    pub fn count(&self, addr: Addr) -> u64 {
+        self.executed.get(&addr).copied().unwrap_or(0)
+    }
+
+    pub fn branch(&self, addr: Addr) -> Option<BranchCoverage> {
+        self.branches.get(&addr).copied()
+    }
+
+    pub(crate) fn record_execute(&mut self, addr: Addr) {
+        *self.executed.entry(addr).or_default() += 1;
+    }
+
+    pub(crate) fn record_branch(&mut self, addr: Addr, taken: bool) {
+        let branch = self.branches.entry(addr).or_default();
+
+        if taken {
+            branch.taken += 1;
+        } else {
+            branch.not_taken += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use crate::registers::constants::*;
+
+    #[test]
+    fn can_cover() {
+        let mut memory = Memory::with_capacity(0x10000);
+
+        // move lit (0x0002) reg (acc)
+        // dec reg (acc)
+        // jne lit (0x0000) mem (addr 0x0004)
+        memory.set_bytes(&[
+            MOV_LIT_REG, 0x00, 0x02, ACC,
+            DEC_REG, ACC,
+            JNE_LIT, 0x00, 0x00, 0x00, 0x04,
+            HLT,
+        ]);
+
+        let mut cpu = Cpu::from(memory);
+        cpu.enable_coverage();
+        cpu.run();
+
+        let coverage = cpu.take_coverage().unwrap();
+
+        assert_eq!(coverage.executed().iter().collect::<Vec<_>>(), vec![
+            (&0x0000, &1),
+            (&0x0004, &2),
+            (&0x0006, &2),
+            (&0x000B, &1),
+        ]);
+        assert_eq!(coverage.branch(0x0006), Some(BranchCoverage {
+            taken: 1,
+            not_taken: 1,
+        }));
+        assert_eq!(coverage.branch(0x0004), None);
+        assert!(cpu.coverage().is_none());
+    }
+}
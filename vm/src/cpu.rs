@@ -1,22 +1,43 @@
+use crate::decode::{DecodeCache, Decoded};
+use crate::memory::{read_only_fault, BusWrite};
 use crate::prelude::*;
-use std::collections::BTreeMap;
 
 #[cfg(test)]
 use hex_slice::AsHex;
 
+/// Where `sp` and `fp` start; the stack grows down from here.
+pub(crate) const STACK_TOP: Addr = 0xffff - 1;
+
+/// A call frame written by `stack_push_state`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Frame {
+    pub fp: Addr,
+    pub return_addr: Addr,
+}
+
 #[derive(Debug)]
 pub struct Cpu {
+    cache: Option<DecodeCache>,
+    costs: CycleCosts,
+    coverage: Option<Coverage>,
+    current: Option<Decoded>,
+    cycles: u64,
+    debugger: Debugger,
     frame_size: Short,
+    history: Option<History>,
+    interrupt_handler: Option<Addr>,
     mapper: MemoryMapper,
-    registers: BTreeMap<RegisterVariant, Register>,
+    observer: Option<Box<dyn CpuObserver>>,
+    registers: [Short; RegisterVariant::VARIANTS.len()],
+    stop: Option<StopReason>,
 }
 
 impl Cpu {
     #[cfg(test)]
     fn debug(&self) {
-        println!("");
-        for (reg, register) in self.registers.iter() {
-            println!("{:?}: {:02X}", reg, &register.memory.0[..].as_hex())
+        println!();
+        for (reg, val) in self.registers() {
+            println!("{:?}: {:02X}", reg, &val.to_be_bytes()[..].as_hex())
         }
 
         let ip: Addr = self.get_register_val(RegisterVariant::Ip);
@@ -36,33 +57,23 @@ impl Cpu {
         // println!("stack: {:02X}", stack.as_hex());
     }
 
-    fn create_registers() -> BTreeMap<RegisterVariant, Register> {
-        let mut registers = BTreeMap::new();
-
-        registers.insert(RegisterVariant::Ip, Register::new());
-        registers.insert(RegisterVariant::Acc, Register::new());
-        registers.insert(RegisterVariant::R1, Register::new());
-        registers.insert(RegisterVariant::R2, Register::new());
-        registers.insert(RegisterVariant::R3, Register::new());
-        registers.insert(RegisterVariant::R4, Register::new());
-        registers.insert(RegisterVariant::R5, Register::new());
-        registers.insert(RegisterVariant::R6, Register::new());
-        registers.insert(RegisterVariant::R7, Register::new());
-        registers.insert(RegisterVariant::R8, Register::new());
-        registers.insert(RegisterVariant::Sp, Register::new());
-        registers.insert(RegisterVariant::Fp, Register::new());
+    fn create_registers() -> [Short; RegisterVariant::VARIANTS.len()] {
+        let mut registers = [0; RegisterVariant::VARIANTS.len()];
 
-        registers.get_mut(&RegisterVariant::Sp).unwrap()
-            .set_u16(0x0000, 0xffff - 1);
-
-        registers.get_mut(&RegisterVariant::Fp).unwrap()
-            .set_u16(0x0000, 0xffff - 1);
+        registers[RegisterVariant::Sp.index()] = STACK_TOP;
+        registers[RegisterVariant::Fp.index()] = STACK_TOP;
 
         registers
     }
 
-    fn get_register_val(&self, reg: RegisterVariant) -> Short {
-        self.registers.get(&reg).unwrap().get_u16(0x0000)
+    pub(crate) fn get_register_val(&self, reg: RegisterVariant) -> Short {
+        self.registers[reg.index()]
+    }
+
+    /// Sets a register from outside the program, e.g. from a debugger, without
+    /// notifying the observer or recording history.
+    pub(crate) fn poke_register_val(&mut self, reg: RegisterVariant, val: Short) {
+        self.registers[reg.index()] = val;
     }
 
     fn fetch_register_val(&mut self) -> Short {
@@ -71,24 +82,174 @@ impl Cpu {
     }
 
     fn set_register_val(&mut self, reg: RegisterVariant, val: Short) {
-        self.registers.get_mut(&reg).unwrap().set_u16(0x0000, val);
+        let old = self.registers[reg.index()];
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_register_write(reg, old, val);
+        }
+
+        if let Some(history) = self.history.as_mut() {
+            history.record_register(reg, old);
+        }
+
+        self.registers[reg.index()] = val;
+    }
+
+    /// Moves `ip` past the bytes just fetched. Unlike `set_register_val`
+    /// this isn't reported to the observer, which would otherwise hear about
+    /// every byte of every instruction.
+    fn advance_ip(&mut self, len: Addr) {
+        let ip = self.registers[RegisterVariant::Ip.index()];
+
+        if let Some(history) = self.history.as_mut() {
+            history.record_register(RegisterVariant::Ip, ip);
+        }
+
+        self.registers[RegisterVariant::Ip.index()] = ip + len;
+    }
+
+    fn mem_get_u16(&mut self, addr: Addr) -> Short {
+        let region = self.mapper.find_region_from_addr(addr);
+        let val = region.get_u16(addr);
+
+        self.cycles += self.costs.memory_access + region.access_cycles();
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_read(addr, MemoryAccess::Short(val));
+        }
+
+        if let Some(watched) = self.debugger.watched(addr, 2, WatchKind::Read) {
+            self.stop = Some(StopReason::Watch {
+                addr: watched,
+                kind: WatchKind::Read,
+                old: Some(val),
+                new: val,
+            });
+        }
+
+        val
+    }
+
+    fn mem_set_u16(&mut self, addr: Addr, val: Short) {
+        let region = self.mapper.find_region_from_addr(addr);
+        self.cycles += self.costs.memory_access + region.access_cycles();
+
+        if region.read_only() {
+            self.stop = Some(StopReason::Fault(read_only_fault(addr)));
+            return;
+        }
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_write(addr, MemoryAccess::Short(val));
+        }
+
+        if let Some(watched) = self.debugger.watched(addr, 2, WatchKind::Write) {
+            let region = self.mapper.find_region_from_addr(addr);
+            let old = if region.is_memory() { Some(region.get_u16(addr)) } else { None };
+
+            self.stop = Some(StopReason::Watch {
+                addr: watched,
+                kind: WatchKind::Write,
+                old,
+                new: val,
+            });
+        }
+
+        // reading a device register can change the device, and writing the
+        // old value back wouldn't put the device back as it was anyway
+        if let Some(history) = self.history.as_mut() {
+            let region = self.mapper.find_region_from_addr(addr);
+
+            if region.is_memory() {
+                history.record_memory(addr, MemoryAccess::Short(region.get_u16(addr)));
+            }
+        }
+
+        self.invalidate(addr, 2);
+        self.mapper.find_region_from_addr_mut(addr).set_u16(addr, val);
+    }
+
+    fn invalidate(&mut self, addr: Addr, len: usize) {
+        if let Some(cache) = self.cache.as_mut() {
+            cache.invalidate(addr, len);
+        }
+    }
+
+    /// The instruction at `ip` from the decode cache, decoding and caching it
+    /// first if need be. `None` if caching is off or the instruction isn't
+    /// in a single cacheable region.
+    fn decode(&mut self, ip: Addr) -> Option<Decoded> {
+        let cache = self.cache.as_mut()?;
+
+        if let Some(decoded) = cache.get(ip) {
+            return Some(decoded);
+        }
+
+        let region = self.mapper.find_region_from_addr(ip);
+        if !region.cacheable() {
+            return None;
+        }
+
+        let opcode = region.get_u8(ip);
+        let variant = InstructionVariant::from(opcode);
+        let len = 1 + InstructionArguments::from(variant).bytes();
+
+        if !region.range().contains(&(ip as usize + len as usize - 1)) {
+            return None;
+        }
+
+        let mut decoded = Decoded {
+            addr: ip,
+            bytes: [0; 5],
+            len,
+            variant,
+        };
+
+        for (i, byte) in decoded.bytes.iter_mut().enumerate().take(len as usize) {
+            *byte = region.get_u8(ip + i as Addr);
+        }
+
+        cache.insert(decoded);
+        Some(decoded)
     }
 
     fn fetch_u8(&mut self) -> Byte {
         let ip: Addr = self.get_register_val(RegisterVariant::Ip);
-        self.set_register_val(RegisterVariant::Ip, ip + 0x0001);
+        self.advance_ip(0x0001);
+
+        let val = match self.current.and_then(|decoded| decoded.byte_at(ip)) {
+            Some(val) => val,
+            None => self.mapper.find_region_from_addr(ip).get_u8(ip),
+        };
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_read(ip, MemoryAccess::Byte(val));
+        }
 
-        self.get_u8(ip)
+        val
     }
 
     fn fetch_u16(&mut self) -> Short {
         let ip: Addr = self.get_register_val(RegisterVariant::Ip);
-        self.set_register_val(RegisterVariant::Ip, ip + 0x0002);
+        self.advance_ip(0x0002);
 
-        self.get_u16(ip)
+        let cached = self.current.and_then(|decoded| {
+            Some(((decoded.byte_at(ip)? as Short) << 8) | decoded.byte_at(ip + 1)? as Short)
+        });
+
+        let val = match cached {
+            Some(val) => val,
+            None => self.mapper.find_region_from_addr(ip).get_u16(ip),
+        };
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_read(ip, MemoryAccess::Short(val));
+        }
+
+        val
     }
 
-    fn execute(&mut self, instruction: InstructionVariant) -> bool {
+    fn execute(&mut self, ip: Addr, instruction: InstructionVariant) -> bool {
         match instruction {
             InstructionVariant::MoveLitReg => {
                 let val = self.fetch_u16();
@@ -103,30 +264,30 @@ impl Cpu {
             InstructionVariant::MoveRegMem => {
                 let val = self.fetch_register_val();
                 let addr = self.fetch_u16();
-                self.set_u16(addr, val);
+                self.mem_set_u16(addr, val);
             },
             InstructionVariant::MoveMemReg => {
                 let addr = self.fetch_u16();
-                let val = self.get_u16(addr);
+                let val = self.mem_get_u16(addr);
                 let reg: RegisterVariant = self.fetch_u8().into();
                 self.set_register_val(reg, val);
             },
             InstructionVariant::MoveLitMem => {
                 let val = self.fetch_u16();
                 let addr = self.fetch_u16();
-                self.set_u16(addr, val);
+                self.mem_set_u16(addr, val);
             },
             InstructionVariant::MoveRegPtrReg => {
                 let addr = self.fetch_register_val();
                 let reg: RegisterVariant = self.fetch_u8().into();
-                let val = self.get_u16(addr);
+                let val = self.mem_get_u16(addr);
                 self.set_register_val(reg, val);
             },
             InstructionVariant::MoveLitOffReg => {
                 let addr = self.fetch_u16();
                 let offset = self.fetch_register_val();
                 let reg: RegisterVariant = self.fetch_u8().into();
-                let val = self.get_u16(addr + offset);
+                let val = self.mem_get_u16(addr + offset);
                 self.set_register_val(reg, val);
             },
 
@@ -326,108 +487,84 @@ impl Cpu {
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val != acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val != acc, addr);
             },
             InstructionVariant::JumpNotEqLit => {
                 let val = self.fetch_u16();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val != acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val != acc, addr);
             },
             InstructionVariant::JumpEqReg => {
                 let val = self.fetch_register_val();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val == acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val == acc, addr);
             },
             InstructionVariant::JumpEqLit => {
                 let val = self.fetch_u16();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val == acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val == acc, addr);
             },
             InstructionVariant::JumpLtReg => {
                 let val = self.fetch_register_val();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val < acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val < acc, addr);
             },
             InstructionVariant::JumpLtLit => {
                 let val = self.fetch_u16();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val < acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val < acc, addr);
             },
             InstructionVariant::JumpGtReg => {
                 let val = self.fetch_register_val();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val > acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val > acc, addr);
             },
             InstructionVariant::JumpGtLit => {
                 let val = self.fetch_u16();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val > acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val > acc, addr);
             },
             InstructionVariant::JumpLteReg => {
                 let val = self.fetch_register_val();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val <= acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val <= acc, addr);
             },
             InstructionVariant::JumpLteLit => {
                 let val = self.fetch_u16();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val <= acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val <= acc, addr);
             },
             InstructionVariant::JumpGteReg => {
                 let val = self.fetch_register_val();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val >= acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val >= acc, addr);
             },
             InstructionVariant::JumpGteLit => {
                 let val = self.fetch_u16();
                 let addr = self.fetch_u16();
 
                 let acc = self.get_register_val(RegisterVariant::Acc);
-                if val >= acc {
-                    self.set_register_val(RegisterVariant::Ip, addr);
-                }
+                self.jump_if(ip, val >= acc, addr);
             },
 
             InstructionVariant::PushLit => {
@@ -464,9 +601,21 @@ impl Cpu {
         false
     }
 
+    /// Jumps to `addr` if `taken`, recording which way the conditional jump
+    /// at `ip` went.
+    fn jump_if(&mut self, ip: Addr, taken: bool, addr: Addr) {
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record_branch(ip, taken);
+        }
+
+        if taken {
+            self.set_register_val(RegisterVariant::Ip, addr);
+        }
+    }
+
     fn stack_push(&mut self, val: Short) {
         let sp: Addr = self.get_register_val(RegisterVariant::Sp);
-        self.set_u16(sp, val);
+        self.mem_set_u16(sp, val);
         self.set_register_val(RegisterVariant::Sp, sp - 2);
 
         self.frame_size += 2;
@@ -489,14 +638,22 @@ impl Cpu {
         self.set_register_val(RegisterVariant::Fp, sp);
 
         self.frame_size = 0;
+
+        if let Some(observer) = self.observer.as_mut() {
+            let ip: Addr = self.registers[RegisterVariant::Ip.index()];
+            observer.on_call(ip, sp);
+        }
     }
 
     fn stack_pop(&mut self) -> u16 {
         let sp: Addr = self.get_register_val(RegisterVariant::Sp) + 2;
         self.set_register_val(RegisterVariant::Sp, sp);
-        self.frame_size -= 2;
 
-        self.get_u16(sp)
+        // popping the saved frame size in `stack_pop_state` underflows when
+        // the subroutine pushed nothing; it's overwritten straight after
+        self.frame_size = self.frame_size.wrapping_sub(2);
+
+        self.mem_get_u16(sp)
     }
 
     fn stack_pop_state(&mut self) {
@@ -530,18 +687,402 @@ impl Cpu {
         for _ in 0..n_args { self.stack_pop(); }
 
         self.set_register_val(RegisterVariant::Fp, fp + frame_size);
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_return(ip);
+        }
+    }
+
+    /// Where to call when a device interrupts. Interrupts raised while there
+    /// is no handler are dropped.
+    pub fn set_interrupt_handler(&mut self, handler: Option<Addr>) {
+        self.interrupt_handler = handler;
+    }
+
+    pub fn interrupt_handler(&self) -> Option<Addr> {
+        self.interrupt_handler
+    }
+
+    /// Lets devices use the bus, stops on the first device fault, then calls
+    /// the interrupt handler, with no arguments, if any device asked for it.
+    /// The handler returns with `ret` like any other subroutine; registers it
+    /// doesn't save itself are restored when it does. Devices interrupting at
+    /// the same time share one call, so the handler has to check each of
+    /// them.
+    fn poll_devices(&mut self, halted: bool) {
+        let (cycles, written) = self.mapper.run_bus(self.costs.memory_access);
+
+        self.cycles += cycles;
+        for write in written {
+            self.bus_written(write);
+        }
+
+        // every device is asked, so none of them is left with a stale
+        // interrupt or fault
+        let mut interrupted = false;
+        let mut fault = None;
+        for region in self.mapper.regions_mut() {
+            interrupted |= region.take_interrupt();
+            fault = fault.or(region.take_fault());
+        }
+
+        if let Some(fault) = fault {
+            self.stop = Some(StopReason::Fault(fault));
+        }
+
+        let handler = match self.interrupt_handler {
+            Some(handler) if !halted => handler,
+            _ => return,
+        };
+
+        if interrupted {
+            self.stack_push(0);
+            self.stack_push_state();
+            self.set_register_val(RegisterVariant::Ip, handler);
+        }
+    }
+
+    /// Reports a byte a device wrote over the bus the way `mem_set_u16`
+    /// reports the program's own writes, so it can be watched and undone.
+    fn bus_written(&mut self, write: BusWrite) {
+        let BusWrite { addr, old, new } = write;
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_write(addr, MemoryAccess::Byte(new));
+        }
+
+        if let Some(watched) = self.debugger.watched(addr, 1, WatchKind::Write) {
+            self.stop.get_or_insert(StopReason::Watch {
+                addr: watched,
+                kind: WatchKind::Write,
+                old: old.map(Short::from),
+                new: new as Short,
+            });
+        }
+
+        if let (Some(history), Some(old)) = (self.history.as_mut(), old) {
+            history.record_memory(addr, MemoryAccess::Byte(old));
+        }
+
+        self.invalidate(addr, 1);
+    }
+
+    /// Turns the decode cache on or off. It's on by default; with it on,
+    /// instructions in cacheable regions are only decoded the first time they
+    /// run, or again after something writes over them.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.cache = if enabled { Some(DecodeCache::default()) } else { None };
+    }
+
+    pub fn set_observer(&mut self, observer: Box<dyn CpuObserver>) {
+        self.observer = Some(observer);
+    }
+
+    pub fn take_observer(&mut self) -> Option<Box<dyn CpuObserver>> {
+        self.observer.take()
     }
 
     pub fn step(&mut self) -> bool {
         #[cfg(test)]
         self.debug();
 
-        let instruction: InstructionVariant = self.fetch_u8().into();
-        self.execute(instruction)
+        if let Some(history) = self.history.as_mut() {
+            history.begin(self.frame_size, self.cycles);
+        }
+
+        let start = self.cycles;
+
+        let ip: Addr = self.get_register_val(RegisterVariant::Ip);
+
+        self.current = self.decode(ip);
+        let opcode = self.fetch_u8();
+
+        let instruction = match self.current {
+            Some(decoded) => decoded.variant,
+            None => opcode.into(),
+        };
+
+        self.cycles += self.costs.base(instruction);
+
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record_execute(ip);
+        }
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.before_execute(ip, instruction);
+        }
+
+        let halted = self.execute(ip, instruction);
+        self.current = None;
+
+        let spent = self.cycles - start;
+        for region in self.mapper.regions_mut() {
+            region.tick(spent);
+        }
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.after_execute(ip, instruction);
+        }
+
+        self.poll_devices(halted);
+
+        halted
     }
 
-    pub fn run(&mut self) {
-        while !self.step() {}
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cycles: self.cycles,
+            frame_size: self.frame_size,
+            interrupt_handler: self.interrupt_handler,
+            registers: self.registers().collect(),
+            regions: self.mapper.regions().iter()
+                .map(|region| RegionSnapshot {
+                    range: region.range().clone(),
+                    state: region.save_state(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores a `Snapshot` taken from a `Cpu` with the same memory layout.
+    /// Nothing is changed if the layouts don't match, or if any region
+    /// rejects its saved state.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        let regions = self.mapper.regions_mut();
+
+        let matches = regions.len() == snapshot.regions.len() &&
+            regions.iter()
+                .zip(snapshot.regions.iter())
+                .all(|(region, saved)| *region.range() == saved.range);
+
+        if !matches {
+            return Err(SnapshotError::new("snapshot memory layout does not match"));
+        }
+
+        // a device only finds out whether the state suits it by loading it,
+        // so keep what every region held to put back if one of them refuses
+        let previous = regions.iter().map(MemoryRegion::save_state).collect::<Vec<_>>();
+
+        for (i, saved) in snapshot.regions.iter().enumerate() {
+            if let Err(err) = regions[i].load_state(&saved.state) {
+                for (region, state) in regions.iter_mut().zip(previous.iter()).take(i + 1) {
+                    region.load_state(state)
+                        .expect("cannot put back the state the region just saved");
+                }
+
+                return Err(err);
+            }
+        }
+
+        for (reg, val) in snapshot.registers.iter() {
+            self.registers[reg.index()] = *val;
+        }
+
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
+
+        // what's recorded leads up to the state that was just replaced
+        if let Some(history) = self.history.as_mut() {
+            *history = History::with_capacity(history.capacity());
+        }
+
+        self.cycles = snapshot.cycles;
+        self.frame_size = snapshot.frame_size;
+        self.interrupt_handler = snapshot.interrupt_handler;
+        self.stop = None;
+
+        Ok(())
+    }
+
+    /// Starts recording the previous value of every register and memory
+    /// write, keeping the last `capacity` instructions so they can be undone
+    /// with `step_back`. Only writes to memory are recorded: devices are
+    /// left as they are by `step_back`, and can be put back with `snapshot`
+    /// and `restore` instead.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::with_capacity(capacity));
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Undoes the last recorded instruction. Returns `false` if there is
+    /// nothing left to undo.
+    pub fn step_back(&mut self) -> bool {
+        let entry = match self.history.as_mut().and_then(History::pop) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        for (addr, old) in entry.memory.into_iter().rev() {
+            let region = self.mapper.find_region_from_addr_mut(addr);
+
+            match old {
+                MemoryAccess::Byte(old) => region.set_u8(addr, old),
+                MemoryAccess::Short(old) => region.set_u16(addr, old),
+            }
+
+            self.invalidate(addr, 2);
+        }
+
+        for (reg, old) in entry.registers.into_iter().rev() {
+            self.registers[reg.index()] = old;
+        }
+
+        self.cycles = entry.cycles;
+        self.frame_size = entry.frame_size;
+        self.stop = None;
+
+        true
+    }
+
+    /// Steps backwards until `ip` is `addr`. Returns `false` if the history
+    /// ran out first.
+    pub fn run_back_to(&mut self, addr: Addr) -> bool {
+        while self.step_back() {
+            if self.get_register_val(RegisterVariant::Ip) == addr {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Starts recording which instructions run and which way conditional
+    /// jumps go, discarding anything recorded before.
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Stops recording coverage and hands back what was recorded.
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Cycles spent, according to the `CycleCosts`, since the `Cpu` was
+    /// created or `reset_cycles` was last called.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn reset_cycles(&mut self) {
+        self.cycles = 0;
+    }
+
+    pub fn cycle_costs(&self) -> &CycleCosts {
+        &self.costs
+    }
+
+    pub fn set_cycle_costs(&mut self, costs: CycleCosts) {
+        self.costs = costs;
+    }
+
+    pub fn register(&self, reg: RegisterVariant) -> Short {
+        self.get_register_val(reg)
+    }
+
+    /// Every register and its value, in `RegisterVariant::VARIANTS` order.
+    pub fn registers(&self) -> impl Iterator<Item = (RegisterVariant, Short)> + '_ {
+        RegisterVariant::VARIANTS.iter()
+            .map(move |reg| (*reg, self.registers[reg.index()]))
+    }
+
+    /// Walks the frames `stack_push_state` has written, innermost first.
+    pub fn frames(&self) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut fp: Addr = self.get_register_val(RegisterVariant::Fp);
+
+        while fp < STACK_TOP {
+            // a corrupt `fp` can point anywhere, so only read what's there
+            let last = match fp.checked_add(5) {
+                Some(last) => last,
+                None => break,
+            };
+
+            if !(fp + 2..=last).all(|addr| self.is_mapped(addr)) {
+                break;
+            }
+
+            let frame_size = self.get_u16(fp + 2);
+
+            frames.push(Frame {
+                fp,
+                return_addr: self.get_u16(fp + 4),
+            });
+
+            fp = match fp.checked_add(frame_size) {
+                Some(caller) if caller > fp => caller,
+                _ => break,
+            };
+        }
+
+        frames
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// Whether `addr` can be read or written; `get_u8` and friends panic on
+    /// addresses that aren't.
+    pub fn is_mapped(&self, addr: Addr) -> bool {
+        self.mapper.is_mapped(addr)
+    }
+
+    /// Runs until the program halts or hits a breakpoint or watchpoint. The
+    /// instruction at the current `ip` always runs, so calling `run` again
+    /// after a breakpoint resumes past it.
+    pub fn run(&mut self) -> StopReason {
+        self.run_steps(None, true).unwrap()
+    }
+
+    /// Like `run`, but gives up after `max_instructions` instructions so a
+    /// program that never halts can't hang the caller.
+    pub fn run_for(&mut self, max_instructions: u64) -> StopReason {
+        self.run_steps(Some(max_instructions), true)
+            .unwrap_or(StopReason::BudgetExhausted)
+    }
+
+    /// Runs at most `max_steps` instructions, returning `None` if none of
+    /// them stopped execution. The breakpoint at the current `ip` is only
+    /// honoured if `resume` is `false`.
+    pub(crate) fn run_steps(&mut self, max_steps: Option<u64>, resume: bool) -> Option<StopReason> {
+        self.stop = None;
+
+        let mut steps = 0;
+
+        while max_steps.is_none_or(|max_steps| steps < max_steps) {
+            let ip: Addr = self.get_register_val(RegisterVariant::Ip);
+            if (steps > 0 || !resume) && self.debugger.has_breakpoint(ip) {
+                return Some(StopReason::Breakpoint(ip));
+            }
+
+            steps += 1;
+
+            if self.step() {
+                return Some(StopReason::Halt);
+            }
+
+            if let Some(reason) = self.stop.take() {
+                return Some(reason);
+            }
+        }
+
+        None
     }
 }
 
@@ -557,10 +1098,12 @@ impl Read for Cpu {
 
 impl Write for Cpu {
     fn set_u8(&mut self, addr: Addr, val: Byte) {
+        self.invalidate(addr, 1);
         self.mapper.find_region_from_addr_mut(addr).set_u8(addr, val)
     }
 
     fn set_u16(&mut self, addr: Addr, val: Short) {
+        self.invalidate(addr, 2);
         self.mapper.find_region_from_addr_mut(addr).set_u16(addr, val)
     }
 }
@@ -573,15 +1116,25 @@ impl From<Memory> for Cpu {
         mm.add_region(
             MemoryRegion::builder()
                 .range(memory.get_range())
-                .device(Box::new(memory))
+                .memory(memory)
                 .finalize()
                 .unwrap()
-        );
+        ).unwrap();
 
         Self {
+            cache: Some(DecodeCache::default()),
+            costs: CycleCosts::default(),
+            coverage: None,
+            current: None,
+            cycles: 0,
+            debugger: Debugger::default(),
             frame_size: 0,
+            history: None,
+            interrupt_handler: None,
             mapper: mm,
+            observer: None,
             registers: Self::create_registers(),
+            stop: None,
         }
     }
 }
@@ -589,9 +1142,19 @@ impl From<Memory> for Cpu {
 impl From<MemoryMapper> for Cpu {
     fn from(mm: MemoryMapper) -> Self {
         Self {
+            cache: Some(DecodeCache::default()),
+            costs: CycleCosts::default(),
+            coverage: None,
+            current: None,
+            cycles: 0,
+            debugger: Debugger::default(),
             frame_size: 0,
+            history: None,
+            interrupt_handler: None,
             mapper: mm,
+            observer: None,
             registers: Self::create_registers(),
+            stop: None,
         }
     }
 }
@@ -908,6 +1471,8 @@ mod tests {
         // now in subroutine
         println!("now in subroutine");
 
+        assert_eq!(cpu.frames(), vec![ Frame { fp: 0xffe2, return_addr: 0x0017 } ]);
+
         cpu.step();
         cpu.step();
         cpu.step();
@@ -967,4 +1532,32 @@ mod tests {
         assert_eq!(cpu.get_register_val(RegisterVariant::R2), 0x001E);
         assert_eq!(cpu.get_register_val(RegisterVariant::Ip), bytes.len() as Addr);
     }
+
+    #[test]
+    fn can_read_registers() {
+        let mut cpu = Cpu::from(Memory::with_capacity(0x100));
+        cpu.set_register_val(RegisterVariant::R3, 0x1234);
+
+        let registers: Vec<(RegisterVariant, Short)> = cpu.registers().collect();
+
+        assert_eq!(registers.len(), RegisterVariant::VARIANTS.len());
+        assert_eq!(registers[0], (RegisterVariant::Ip, 0x0000));
+        assert_eq!(registers[4], (RegisterVariant::R3, 0x1234));
+        assert_eq!(registers[10], (RegisterVariant::Sp, STACK_TOP));
+        assert_eq!(cpu.register(RegisterVariant::Fp), STACK_TOP);
+    }
+
+    #[test]
+    fn cannot_walk_corrupt_frames() {
+        let mut cpu = Cpu::from(Memory::with_capacity(0x100));
+
+        cpu.poke_register_val(RegisterVariant::Fp, 0xfffd);
+        assert_eq!(cpu.frames(), vec![]);
+
+        cpu.poke_register_val(RegisterVariant::Fp, 0x00fc);
+        assert_eq!(cpu.frames(), vec![]);
+
+        cpu.poke_register_val(RegisterVariant::Fp, 0x00f0);
+        assert_eq!(cpu.frames(), vec![ Frame { fp: 0x00f0, return_addr: 0x0000 } ]);
+    }
 }
@@ -0,0 +1,227 @@
+use crate::prelude::*;
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(&self, kind: WatchKind) -> bool {
+        *self == WatchKind::ReadWrite || *self == kind
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<Addr>,
+    pub kind: WatchKind,
+}
+
+/// Why `Cpu::run` returned.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StopReason {
+    Halt,
+    /// `ip` reached a breakpoint; the instruction there has not run yet.
+    Breakpoint(Addr),
+    /// The instruction that just ran touched a watched address. For reads,
+    /// `old` and `new` are both the value that was read. For writes, `old`
+    /// is only known for RAM; reading a device first could change it.
+    Watch {
+        addr: Addr,
+        kind: WatchKind,
+        old: Option<Short>,
+        new: Short,
+    },
+    /// `Cpu::run_for` ran out of instructions before anything else stopped
+    /// it. Nothing is lost; running again carries on from `ip`.
+    BudgetExhausted,
+    /// A device refused what the instruction that just ran asked of it.
+    Fault(DeviceFault),
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<Addr>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl Debugger {
+    pub fn add_breakpoint(&mut self, addr: Addr) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: Addr) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn has_breakpoint(&self, addr: Addr) -> bool {
+        !self.breakpoints.is_empty() && self.breakpoints.contains(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = Addr> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<Addr>, kind: WatchKind) {
+        self.watchpoints.push(Watchpoint { range, kind });
+    }
+
+    pub fn remove_watchpoint(&mut self, range: RangeInclusive<Addr>, kind: WatchKind) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|watch| watch.range != range || watch.kind != kind);
+
+        self.watchpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns the first address in `addr..=addr + width - 1` covered by a
+    /// watchpoint of the given kind.
+    pub fn watched(&self, addr: Addr, width: Addr, kind: WatchKind) -> Option<Addr> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+
+        (0..width)
+            .map(|offset| addr.wrapping_add(offset))
+            .find(|addr| {
+                self.watchpoints.iter().any(|watch| {
+                    watch.kind.matches(kind) && watch.range.contains(addr)
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use crate::registers::constants::*;
+
+    #[derive(Debug, Default)]
+    struct Latch(Short);
+
+    impl Read for Latch {
+        fn get_u8(&self, _: Addr) -> Byte { self.0 as Byte }
+        fn get_u16(&self, _: Addr) -> Short { self.0 }
+    }
+
+    impl Write for Latch {
+        fn set_u8(&mut self, _: Addr, val: Byte) { self.0 = val as Short; }
+        fn set_u16(&mut self, _: Addr, val: Short) { self.0 = val; }
+    }
+
+    impl Device for Latch {}
+
+    #[test]
+    fn can_break() {
+        let mut memory = Memory::with_capacity(0x10000);
+
+        // move lit (0x1234) reg (r1)
+        // inc reg (r1)
+        memory.set_bytes(&[
+            MOV_LIT_REG, 0x12, 0x34, R1,
+            INC_REG, R1,
+            HLT,
+        ]);
+
+        let mut cpu = Cpu::from(memory);
+        cpu.debugger_mut().add_breakpoint(0x0004);
+
+        assert_eq!(cpu.run(), StopReason::Breakpoint(0x0004));
+        assert_eq!(cpu.register(RegisterVariant::R1), 0x1234);
+
+        assert_eq!(cpu.run(), StopReason::Halt);
+        assert_eq!(cpu.register(RegisterVariant::R1), 0x1235);
+    }
+
+    #[test]
+    fn can_watch() {
+        let mut memory = Memory::with_capacity(0x10000);
+
+        // move lit (0x1234) mem (addr 0x0100)
+        // move lit (0xABCD) mem (addr 0x3000)
+        // move mem (addr 0x0100) reg (r1)
+        memory.set_bytes(&[
+            MOV_LIT_MEM, 0x12, 0x34, 0x01, 0x00,
+            MOV_LIT_MEM, 0xAB, 0xCD, 0x30, 0x00,
+            MOV_MEM_REG, 0x01, 0x00, R1,
+            HLT,
+        ]);
+
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x3000..=0x3001)
+                .priority(1)
+                .device(Box::new(Latch(0x5555)))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(memory.get_range())
+                .memory(memory)
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        let mut cpu = Cpu::from(mm);
+        cpu.debugger_mut().add_watchpoint(0x0101..=0x0101, WatchKind::ReadWrite);
+        cpu.debugger_mut().add_watchpoint(0x3000..=0x3001, WatchKind::Write);
+
+        assert_eq!(cpu.run(), StopReason::Watch {
+            addr: 0x0101,
+            kind: WatchKind::Write,
+            old: Some(0x0000),
+            new: 0x1234,
+        });
+
+        // the device isn't read to find out what it held
+        assert_eq!(cpu.run(), StopReason::Watch {
+            addr: 0x3000,
+            kind: WatchKind::Write,
+            old: None,
+            new: 0xABCD,
+        });
+
+        assert_eq!(cpu.run(), StopReason::Watch {
+            addr: 0x0101,
+            kind: WatchKind::Read,
+            old: Some(0x1234),
+            new: 0x1234,
+        });
+
+        assert_eq!(cpu.run(), StopReason::Halt);
+    }
+
+    #[test]
+    fn can_exhaust_budget() {
+        let mut memory = Memory::with_capacity(0x10000);
+
+        // inc reg (r1)
+        // jne lit (0x0001) mem (addr 0x0000), forever since acc stays 0
+        memory.set_bytes(&[
+            INC_REG, R1,
+            JNE_LIT, 0x00, 0x01, 0x00, 0x00,
+        ]);
+
+        let mut cpu = Cpu::from(memory);
+
+        assert_eq!(cpu.run_for(10), StopReason::BudgetExhausted);
+        assert_eq!(cpu.register(RegisterVariant::R1), 5);
+        assert_eq!(cpu.register(RegisterVariant::Ip), 0x0000);
+
+        assert_eq!(cpu.run_for(3), StopReason::BudgetExhausted);
+        assert_eq!(cpu.register(RegisterVariant::R1), 7);
+        assert_eq!(cpu.register(RegisterVariant::Ip), 0x0002);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::prelude::*;
+
+/// The longest instruction: an opcode and four operand bytes.
+const MAX_LEN: usize = 5;
+
+/// An instruction's opcode and operand bytes as read from memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Decoded {
+    pub addr: Addr,
+    pub bytes: [Byte; MAX_LEN],
+    pub len: u8,
+    pub variant: InstructionVariant,
+}
+
+impl Decoded {
+    /// The byte at `addr`, if it is part of this instruction.
+    pub fn byte_at(&self, addr: Addr) -> Option<Byte> {
+        let offset = addr.wrapping_sub(self.addr) as usize;
+
+        if offset < self.len as usize {
+            Some(self.bytes[offset])
+        } else {
+            None
+        }
+    }
+}
+
+const PAGE_SIZE: usize = 0x100;
+
+type Page = [Option<Decoded>; PAGE_SIZE];
+
+/// Decoded instructions keyed by the address they start at. Only
+/// instructions that lie within a single cacheable region are kept, and
+/// every write has to be passed to `invalidate` so self-modifying code still
+/// sees its own changes. Pages of entries are only allocated once code in
+/// them runs, so a short run, or one that never leaves a small region,
+/// stays cheap.
+#[derive(Debug, Default)]
+pub(crate) struct DecodeCache {
+    pages: Vec<Option<Box<Page>>>,
+}
+
+impl DecodeCache {
+    pub fn get(&self, addr: Addr) -> Option<Decoded> {
+        let page = self.pages.get(addr as usize / PAGE_SIZE)?.as_ref()?;
+        page[addr as usize % PAGE_SIZE]
+    }
+
+    pub fn insert(&mut self, decoded: Decoded) {
+        if self.pages.is_empty() {
+            self.pages.resize_with(Addr::MAX as usize / PAGE_SIZE + 1, || None);
+        }
+
+        let page = self.pages[decoded.addr as usize / PAGE_SIZE]
+            .get_or_insert_with(|| Box::new([None; PAGE_SIZE]));
+
+        page[decoded.addr as usize % PAGE_SIZE] = Some(decoded);
+    }
+
+    /// Drops every instruction that overlaps `len` bytes written at `addr`.
+    pub fn invalidate(&mut self, addr: Addr, len: usize) {
+        if self.pages.is_empty() {
+            return;
+        }
+
+        let first = addr.saturating_sub(MAX_LEN as Addr - 1);
+        let last = addr.saturating_add(len as Addr - 1);
+
+        for start in first..=last {
+            let page = match self.pages[start as usize / PAGE_SIZE].as_mut() {
+                Some(page) => page,
+                None => continue,
+            };
+
+            let entry = &mut page[start as usize % PAGE_SIZE];
+
+            let overlaps = entry.is_some_and(|decoded| {
+                (0..len).any(|i| decoded.byte_at(addr.wrapping_add(i as Addr)).is_some())
+            });
+
+            if overlaps {
+                *entry = None;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pages.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use crate::registers::constants::*;
+
+    #[test]
+    fn can_modify_cached_code() {
+        let mut memory = Memory::with_capacity(0x10000);
+
+        // inc reg (r1), overwritten with dec reg (r1) on the first pass
+        // move lit (dec reg (r1)) mem (addr 0x0000)
+        // inc reg (r2)
+        // move reg (r2) reg (acc)
+        // jne lit (0x0002) mem (addr 0x0000)
+        memory.set_bytes(&[
+            INC_REG, R1,
+            MOV_LIT_MEM, DEC_REG, R1, 0x00, 0x00,
+            INC_REG, R2,
+            MOV_REG_REG, R2, ACC,
+            JNE_LIT, 0x00, 0x02, 0x00, 0x00,
+            HLT,
+        ]);
+
+        for enabled in [ true, false ] {
+            let mut cpu = Cpu::from(memory.clone());
+            cpu.set_decode_cache(enabled);
+
+            assert_eq!(cpu.run(), StopReason::Halt);
+            assert_eq!(cpu.get_register_val(RegisterVariant::R1), 0);
+            assert_eq!(cpu.get_register_val(RegisterVariant::R2), 2);
+        }
+
+        // writes from outside the program invalidate the cache too
+        let mut cpu = Cpu::from(memory);
+        cpu.run_for(1);
+        cpu.poke_register_val(RegisterVariant::Ip, 0x0000);
+        cpu.set_u8(0x0000, NOT);
+        cpu.run_for(1);
+
+        assert_eq!(cpu.get_register_val(RegisterVariant::R1), !1);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::prelude::*;
+
+const BUSY: Short = 0b01;
+const INTERRUPTS: Short = 0b10;
+
+const COPY: Short = 0x0001;
+const FILL: Short = 0x0002;
+
+/// A DMA controller, ten bytes wide, for moving memory around without an
+/// instruction loop. The program sets the source, destination and length,
+/// then writes a command; the transfer runs over the bus after the
+/// instruction that wrote the command, reaching devices as well as RAM, and
+/// costs the cycles of every byte read and written. It can interrupt once
+/// it's done.
+#[derive(Debug, Default)]
+pub struct Dma {
+    command: Short,
+    destination: Addr,
+    fault: Option<DeviceFault>,
+    interrupts: bool,
+    length: Short,
+    pending: bool,
+    source: Addr,
+    status: Short,
+}
+
+impl Dma {
+    /// Where a copy reads from. A fill writes the lower byte instead.
+    pub const SOURCE: Addr = 0x0000;
+
+    /// Where the transfer writes to.
+    pub const DESTINATION: Addr = 0x0002;
+
+    /// How many bytes to write.
+    pub const LENGTH: Addr = 0x0004;
+
+    /// Writing `0x0001` copies and `0x0002` fills. Reads back the last
+    /// command.
+    pub const COMMAND: Addr = 0x0006;
+
+    /// Bit 0: a command is waiting to run. Bit 1: interrupts when a transfer
+    /// finishes are enabled; write the bit to change it.
+    pub const STATUS: Addr = 0x0008;
+
+    /// How many addresses the device takes up.
+    pub fn len(&self) -> usize {
+        Self::STATUS as usize + 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the waiting command. A copy reads the whole source before
+    /// writing anything, so overlapping ranges come out as if they didn't
+    /// overlap. Addresses wrap around past `0xffff`.
+    fn run(&mut self, bus: &mut Bus<'_>) -> Result<(), DeviceFault> {
+        let source = |i: Short| self.source.wrapping_add(i);
+        let destination = |i: Short| self.destination.wrapping_add(i);
+
+        let data = match self.command {
+            COPY => (0..self.length)
+                .map(|i| bus.get_u8(source(i)))
+                .collect::<Result<Vec<_>, _>>()?,
+            FILL => vec![self.source as Byte; self.length as usize],
+            command => {
+                return Err(DeviceFault::new(format!("unknown DMA command {:#06x?}", command)));
+            },
+        };
+
+        for (i, byte) in (0..self.length).zip(data) {
+            bus.set_u8(destination(i), byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for Dma {
+    fn get_u8(&self, addr: Addr) -> Byte {
+        self.get_register_u8(addr)
+    }
+
+    fn get_u16(&self, addr: Addr) -> Short {
+        match addr {
+            Self::SOURCE => self.source,
+            Self::DESTINATION => self.destination,
+            Self::LENGTH => self.length,
+            Self::COMMAND => self.command,
+            Self::STATUS => self.status | if self.interrupts { INTERRUPTS } else { 0 },
+            _ => 0,
+        }
+    }
+}
+
+impl Write for Dma {
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        self.set_register_u8(addr, val);
+    }
+
+    fn set_u16(&mut self, addr: Addr, val: Short) {
+        match addr {
+            Self::SOURCE => self.source = val,
+            Self::DESTINATION => self.destination = val,
+            Self::LENGTH => self.length = val,
+            Self::COMMAND => {
+                self.command = val;
+                self.status = BUSY;
+            },
+            Self::STATUS => self.interrupts = val & INTERRUPTS != 0,
+            _ => (),
+        }
+    }
+}
+
+impl WordRegisters for Dma {}
+
+impl Device for Dma {
+    fn take_interrupt(&mut self) -> bool {
+        std::mem::replace(&mut self.pending, false)
+    }
+
+    fn take_fault(&mut self) -> Option<DeviceFault> {
+        self.fault.take()
+    }
+
+    fn access_bus(&mut self, bus: &mut Bus<'_>) {
+        if self.status & BUSY == 0 {
+            return;
+        }
+
+        self.status &= !BUSY;
+
+        if let Err(fault) = self.run(bus) {
+            self.fault = Some(fault);
+        }
+
+        self.pending |= self.interrupts;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn cpu(program: &[Byte]) -> (Cpu, Rc<RefCell<FramebufferScreen>>) {
+        let mut bytes = program.to_vec();
+        bytes.resize(0x1000, 0);
+        bytes.extend_from_slice(b"hello");
+
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(&bytes);
+
+        let screen = Rc::new(RefCell::new(FramebufferScreen::new(16, 16)));
+
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x3000..=0x3009)
+                .priority(1)
+                .device(Box::new(Dma::new()))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x4000..=0x40ff)
+                .priority(1)
+                .access_cycles(2)
+                .device(Box::new(screen.clone()))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(memory.get_range())
+                .memory(memory)
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        (Cpu::from(mm), screen)
+    }
+
+    /// move lit (val) mem (addr)
+    fn store(addr: Addr, val: Short) -> Vec<Byte> {
+        let [hi, lo] = val.to_be_bytes();
+        let [addr_hi, addr_lo] = addr.to_be_bytes();
+
+        vec![MOV_LIT_MEM, hi, lo, addr_hi, addr_lo]
+    }
+
+    #[test]
+    fn can_copy() {
+        let program = [
+            store(0x3000, 0x1000),
+            store(0x3002, 0x4010),
+            store(0x3004, 0x0005),
+            store(0x3006, COPY),
+            vec![HLT],
+        ].concat();
+
+        let (mut cpu, screen) = cpu(&program);
+        cpu.set_cycle_costs(CycleCosts::flat(0).with_memory_access(1));
+
+        assert_eq!(cpu.run(), StopReason::Halt);
+        assert_eq!(screen.borrow().render_text().lines().nth(1), Some("hello"));
+
+        // a write for each move, then five reads and five writes to the
+        // slower screen
+        assert_eq!(cpu.cycles(), 4 + 5 + 5 * 3);
+    }
+
+    #[test]
+    fn can_fill() {
+        let program = [
+            store(0x3000, 0x00aa),
+            store(0x3002, 0x2000),
+            store(0x3004, 0x0100),
+            store(0x3008, INTERRUPTS),
+            store(0x3006, FILL),
+            vec![HLT],
+        ].concat();
+
+        let (mut cpu, _) = cpu(&program);
+        assert_eq!(cpu.run(), StopReason::Halt);
+        assert_eq!(cpu.get_u16(0x3008), INTERRUPTS);
+
+        assert_eq!(cpu.get_u8(0x1fff), 0x00);
+        assert_eq!(cpu.get_u8(0x2000), 0xaa);
+        assert_eq!(cpu.get_u8(0x20ff), 0xaa);
+        assert_eq!(cpu.get_u8(0x2100), 0x00);
+    }
+
+    #[test]
+    fn can_fault() {
+        let program = [
+            store(0x3006, 0x0003),
+            vec![HLT],
+        ].concat();
+
+        let (mut cpu, _) = cpu(&program);
+        assert_eq!(cpu.run(), StopReason::Fault(DeviceFault::new("unknown DMA command 0x0003")));
+    }
+}
@@ -0,0 +1,65 @@
+/// Something a program did that a device can't carry out, e.g. an unknown
+/// command or a write to read-only memory. Reported through
+/// `Device::take_fault`, or by the `Cpu` itself, and stops it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DeviceFault(String);
+
+impl std::fmt::Display for DeviceFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for DeviceFault {}
+
+impl DeviceFault {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn can_fault() {
+        let mut memory = Memory::with_capacity(0x10000);
+
+        // move lit (0x4241) mem (screen), an unknown command
+        memory.set_bytes(&[
+            MOV_LIT_MEM, 0x42, 0x41, 0x30, 0x00,
+            HLT,
+        ]);
+
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x3000..=0x30ff)
+                .priority(1)
+                .device(Box::new(ScreenDevice::with_output(16, 16, Vec::new())))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(memory.get_range())
+                .memory(memory)
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        let mut cpu = Cpu::from(mm);
+
+        assert_eq!(
+            cpu.run(),
+            StopReason::Fault(DeviceFault::new("unknown screen command 0x42")),
+        );
+        assert_eq!(cpu.register(RegisterVariant::Ip), 0x0005);
+        assert_eq!(cpu.run(), StopReason::Halt);
+    }
+}
@@ -0,0 +1,383 @@
+use crate::prelude::*;
+use std::fmt::Write as _;
+
+/// The command in the upper byte of a `Short` written to a screen cell. See
+/// `ScreenDevice` for the encoding.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScreenCommand {
+    Plain,
+    Bold,
+    Reset,
+    Underline,
+    Inverse,
+    HideCursor,
+    ShowCursor,
+    ScrollUp(Byte),
+    ScrollDown(Byte),
+    Foreground(Byte),
+    Background(Byte),
+    Clear,
+}
+
+impl ScreenCommand {
+    /// `None` if `command` isn't one. `arg` is the lower byte, which is only
+    /// used by commands that don't draw.
+    pub fn decode(command: Byte, arg: Byte) -> Option<Self> {
+        Some(match command {
+            0x00 => Self::Plain,
+            0x01 => Self::Bold,
+            0x02 => Self::Reset,
+            0x03 => Self::Underline,
+            0x04 => Self::Inverse,
+            0x05 => Self::HideCursor,
+            0x06 => Self::ShowCursor,
+            0x07 => Self::ScrollUp(arg),
+            0x08 => Self::ScrollDown(arg),
+            0x10..=0x1f => Self::Foreground(command - 0x10),
+            0x20..=0x2f => Self::Background(command - 0x20),
+            0xfd => Self::Foreground(arg),
+            0xfe => Self::Background(arg),
+            0xff => Self::Clear,
+            _ => return None,
+        })
+    }
+
+    /// Whether the lower byte is a character to draw, rather than an
+    /// argument.
+    pub fn draws(command: Byte) -> bool {
+        !matches!(command, 0x05..=0x08 | 0xfd | 0xfe)
+    }
+}
+
+/// The SGR parameters selecting colour `index` (0-255).
+pub(crate) fn colour_code(index: Byte, foreground: bool) -> String {
+    let base = if foreground { 30 } else { 40 };
+
+    match index {
+        0..=7 => format!("{}", base + index as u16),
+        8..=15 => format!("{}", base + 60 + index as u16 - 8),
+        _ => format!("{};5;{}", base + 8, index),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Attributes {
+    pub bold: bool,
+    pub underline: bool,
+    pub inverse: bool,
+    pub foreground: Option<Byte>,
+    pub background: Option<Byte>,
+}
+
+impl Attributes {
+    /// An escape that resets the terminal's attributes and then sets these.
+    pub fn to_ansi(&self) -> String {
+        let mut codes = vec!["0".to_string()];
+
+        if self.bold {
+            codes.push("1".into());
+        }
+
+        if self.underline {
+            codes.push("4".into());
+        }
+
+        if self.inverse {
+            codes.push("7".into());
+        }
+
+        if let Some(index) = self.foreground {
+            codes.push(colour_code(index, true));
+        }
+
+        if let Some(index) = self.background {
+            codes.push(colour_code(index, false));
+        }
+
+        format!("\x1B[{}m", codes.join(";"))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Cell {
+    /// Zero if nothing was drawn here.
+    pub char: Byte,
+    pub attributes: Attributes,
+}
+
+/// A character screen that only exists in memory, for tests and headless
+/// runs. Understands the same commands as `ScreenDevice`; what's been drawn
+/// can be inspected cell by cell or rendered as text or ANSI escapes.
+/// Reading a cell gives back its character.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FramebufferScreen {
+    attributes: Attributes,
+    cells: Vec<Cell>,
+    columns: u16,
+    cursor_visible: bool,
+    fault: Option<DeviceFault>,
+}
+
+impl FramebufferScreen {
+    pub fn new(columns: u16, rows: u16) -> Self {
+        Self {
+            attributes: Attributes::default(),
+            cells: vec![Cell::default(); columns as usize * rows as usize],
+            columns,
+            cursor_visible: true,
+            fault: None,
+        }
+    }
+
+    pub fn columns(&self) -> u16 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u16 {
+        match self.columns {
+            0 => 0,
+            columns => (self.cells.len() / columns as usize) as u16,
+        }
+    }
+
+    /// How many addresses the screen takes up, one per cell.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Every cell, a row at a time.
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn cell(&self, x: u16, y: u16) -> Option<&Cell> {
+        if x >= self.columns {
+            return None;
+        }
+
+        self.cells.get(y as usize * self.columns as usize + x as usize)
+    }
+
+    /// What the next character drawn will look like.
+    pub fn attributes(&self) -> Attributes {
+        self.attributes
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// Carries out `val` written to the cell at `addr`, returning the command
+    /// it held.
+    pub fn write_cell(&mut self, addr: Addr, val: Short) -> Result<ScreenCommand, DeviceFault> {
+        if addr as usize >= self.cells.len() {
+            return Err(DeviceFault::new(format!(
+                "cell {:#x?} is off the {}x{} screen",
+                addr,
+                self.columns,
+                self.rows(),
+            )));
+        }
+
+        let (command, char) = ((val >> 0b1000) as Byte, val as Byte);
+
+        let decoded = ScreenCommand::decode(command, char)
+            .ok_or_else(|| DeviceFault::new(format!("unknown screen command {:#04x?}", command)))?;
+
+        match decoded {
+            ScreenCommand::Plain => (),
+            ScreenCommand::Bold => self.attributes.bold = true,
+            ScreenCommand::Reset => self.attributes = Attributes::default(),
+            ScreenCommand::Underline => self.attributes.underline = true,
+            ScreenCommand::Inverse => self.attributes.inverse = true,
+            ScreenCommand::HideCursor => self.cursor_visible = false,
+            ScreenCommand::ShowCursor => self.cursor_visible = true,
+            ScreenCommand::ScrollUp(rows) => self.scroll(rows as isize),
+            ScreenCommand::ScrollDown(rows) => self.scroll(-(rows as isize)),
+            ScreenCommand::Foreground(index) => self.attributes.foreground = Some(index),
+            ScreenCommand::Background(index) => self.attributes.background = Some(index),
+            ScreenCommand::Clear => self.cells.iter_mut().for_each(|cell| *cell = Cell::default()),
+        }
+
+        if ScreenCommand::draws(command) {
+            self.cells[addr as usize] = Cell {
+                char,
+                attributes: self.attributes,
+            };
+        }
+
+        Ok(decoded)
+    }
+
+    /// Moves every row up by `rows`, or down if it's negative, blanking the
+    /// rows left behind.
+    fn scroll(&mut self, rows: isize) {
+        let len = self.cells.len();
+        let shift = (rows.unsigned_abs() * self.columns as usize).min(len);
+
+        if rows > 0 {
+            self.cells.rotate_left(shift);
+            self.cells[len - shift..].iter_mut().for_each(|cell| *cell = Cell::default());
+        } else {
+            self.cells.rotate_right(shift);
+            self.cells[..shift].iter_mut().for_each(|cell| *cell = Cell::default());
+        }
+    }
+
+    fn lines(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells.chunks(self.columns.max(1) as usize)
+    }
+
+    fn printable(char: Byte) -> char {
+        match char {
+            0x20..=0x7e => char as char,
+            _ => ' ',
+        }
+    }
+
+    /// The characters on screen, a line per row, without trailing spaces or
+    /// attributes.
+    pub fn render_text(&self) -> String {
+        let mut text = String::new();
+
+        for line in self.lines() {
+            let chars: String = line.iter().map(|cell| Self::printable(cell.char)).collect();
+            text.push_str(chars.trim_end());
+            text.push('\n');
+        }
+
+        text
+    }
+
+    /// Escapes that clear a terminal and draw the screen on it.
+    pub fn render_ansi(&self) -> String {
+        let mut ansi = String::from("\x1B[2J\x1B[H");
+
+        for (y, line) in self.lines().enumerate() {
+            let mut current = None;
+
+            write!(ansi, "\x1B[{};1H", y + 1).unwrap();
+
+            for cell in line {
+                if current != Some(cell.attributes) {
+                    ansi.push_str(&cell.attributes.to_ansi());
+                    current = Some(cell.attributes);
+                }
+
+                ansi.push(Self::printable(cell.char));
+            }
+
+            ansi.push_str("\x1B[0m");
+        }
+
+        if !self.cursor_visible {
+            ansi.push_str("\x1B[?25l");
+        }
+
+        ansi
+    }
+}
+
+impl Read for FramebufferScreen {
+    fn get_u8(&self, addr: Addr) -> Byte {
+        self.cells.get(addr as usize).map(|cell| cell.char).unwrap_or(0)
+    }
+
+    fn get_u16(&self, addr: Addr) -> Short {
+        self.get_u8(addr) as Short
+    }
+}
+
+impl Write for FramebufferScreen {
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        self.set_u16(addr, val as Short);
+    }
+
+    fn set_u16(&mut self, addr: Addr, val: Short) {
+        if let Err(fault) = self.write_cell(addr, val) {
+            self.fault = Some(fault);
+        }
+    }
+}
+
+impl Device for FramebufferScreen {
+    fn take_fault(&mut self) -> Option<DeviceFault> {
+        self.fault.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(screen: &mut FramebufferScreen, addr: Addr, text: &str) {
+        for (i, char) in text.bytes().enumerate() {
+            screen.set_u16(addr + i as Addr, char as Short);
+        }
+    }
+
+    #[test]
+    fn can_draw() {
+        let mut screen = FramebufferScreen::new(8, 3);
+
+        text(&mut screen, 0x0000, "hello");
+        screen.set_u16(0x0008, 0x1100 | b'w' as Short);
+        text(&mut screen, 0x0009, "orld");
+
+        assert_eq!(screen.render_text(), "hello\nworld\n\n");
+        assert_eq!(screen.get_u16(0x0001), b'e' as Short);
+
+        let red = Attributes {
+            foreground: Some(1),
+            ..Attributes::default()
+        };
+
+        assert_eq!(screen.cell(0, 1), Some(&Cell { char: b'w', attributes: red }));
+        assert_eq!(screen.cell(4, 1), Some(&Cell { char: b'd', attributes: red }));
+        assert_eq!(screen.cell(5, 1), Some(&Cell::default()));
+        assert_eq!(screen.cell(8, 1), None);
+
+        screen.set_u16(0x0000, 0x0500);
+        assert_eq!(
+            screen.render_ansi(),
+            "\x1B[2J\x1B[H\
+             \x1B[1;1H\x1B[0mhello   \x1B[0m\
+             \x1B[2;1H\x1B[0;31mworld\x1B[0m   \x1B[0m\
+             \x1B[3;1H\x1B[0m        \x1B[0m\
+             \x1B[?25l",
+        );
+    }
+
+    #[test]
+    fn can_scroll() {
+        let mut screen = FramebufferScreen::new(2, 3);
+
+        text(&mut screen, 0x0000, "aabbcc");
+        screen.set_u16(0x0000, 0x0701);
+        assert_eq!(screen.render_text(), "bb\ncc\n\n");
+
+        screen.set_u16(0x0000, 0x0802);
+        assert_eq!(screen.render_text(), "\n\nbb\n");
+
+        screen.set_u16(0x0000, 0x0709);
+        assert_eq!(screen.render_text(), "\n\n\n");
+    }
+
+    #[test]
+    fn can_compare() {
+        let mut screen = FramebufferScreen::new(4, 1);
+        let before = screen.clone();
+
+        screen.set_u16(0x0000, 0x4200);
+        assert_eq!(screen.take_fault(), Some(DeviceFault::new("unknown screen command 0x42")));
+        assert_eq!(screen, before);
+
+        screen.set_u16(0x0004, b'a' as Short);
+        assert_eq!(screen.take_fault(), Some(DeviceFault::new("cell 0x4 is off the 4x1 screen")));
+        assert_eq!(screen, before);
+    }
+}
@@ -0,0 +1,561 @@
+//! A stub for the GDB remote serial protocol, so `gdb` (or any front-end that
+//! speaks it) can debug a `Cpu` over TCP:
+//!
+//! ```text
+//! (gdb) set architecture ...
+//! (gdb) target remote localhost:1234
+//! ```
+//!
+//! The stub reports a custom target description with the twelve 16-bit
+//! registers in `RegisterVariant` order, big-endian like the VM itself.
+
+use crate::prelude::*;
+use crate::registers::constants;
+use std::io::{self, Read as IoRead, Write as IoWrite};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.lljs.vm">
+    <reg name="ip" bitsize="16" type="code_ptr" regnum="0"/>
+    <reg name="acc" bitsize="16" type="uint16"/>
+    <reg name="r1" bitsize="16" type="uint16"/>
+    <reg name="r2" bitsize="16" type="uint16"/>
+    <reg name="r3" bitsize="16" type="uint16"/>
+    <reg name="r4" bitsize="16" type="uint16"/>
+    <reg name="r5" bitsize="16" type="uint16"/>
+    <reg name="r6" bitsize="16" type="uint16"/>
+    <reg name="r7" bitsize="16" type="uint16"/>
+    <reg name="r8" bitsize="16" type="uint16"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="fp" bitsize="16" type="data_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// How many instructions to run between checks for an interrupt (`^C`) from
+/// the debugger.
+const POLL_INTERVAL: u64 = 0x1000;
+
+/// How a debugging session ended.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SessionEnd {
+    /// The debugger detached or disconnected, leaving the program to carry
+    /// on.
+    Detached,
+    /// The program halted while the debugger was attached.
+    Halted,
+    /// The debugger killed the program, which shouldn't run any further.
+    Killed,
+}
+
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+impl GdbServer {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for a debugger to attach, then serves it until it detaches,
+    /// kills the target, or disconnects.
+    pub fn serve(&self, cpu: &mut Cpu) -> io::Result<SessionEnd> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nodelay(true)?;
+
+        let mut session = Session {
+            cpu,
+            stream,
+            halted: false,
+            killed: false,
+        };
+
+        session.run()?;
+
+        Ok(match (session.killed, session.halted) {
+            (true, _) => SessionEnd::Killed,
+            (false, true) => SessionEnd::Halted,
+            (false, false) => SessionEnd::Detached,
+        })
+    }
+}
+
+enum Reply {
+    Packet(String),
+    Close(Option<String>),
+}
+
+struct Session<'a> {
+    cpu: &'a mut Cpu,
+    stream: TcpStream,
+    halted: bool,
+    killed: bool,
+}
+
+impl<'a> Session<'a> {
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(&packet)? {
+                Reply::Packet(reply) => self.write_packet(&reply)?,
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        self.write_packet(&reply)?;
+                    }
+
+                    break;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0; 1];
+        match self.stream.read(&mut buf)? {
+            0 => Ok(None),
+            _ => Ok(Some(buf[0])),
+        }
+    }
+
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // skip acks and anything else between packets
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'$') => break,
+                    Some(_) => (),
+                }
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+
+            let expected = std::str::from_utf8(&checksum).ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+            if expected == Some(checksum_of(&data)) {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            self.stream.flush()?;
+
+            match self.read_byte()? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &str) -> io::Result<Reply> {
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => self.stop_reply(None),
+            Some(b'g') => RegisterVariant::VARIANTS.iter()
+                .map(|reg| format!("{:04x}", self.cpu.get_register_val(*reg)))
+                .collect(),
+            Some(b'G') => {
+                let values = &packet[1..];
+                if !values.is_ascii() || values.len() != RegisterVariant::VARIANTS.len() * 4 {
+                    return Ok(Reply::Packet("E01".into()));
+                }
+
+                for (i, reg) in RegisterVariant::VARIANTS.iter().enumerate() {
+                    match u16::from_str_radix(&values[i * 4..i * 4 + 4], 16) {
+                        Ok(val) => self.cpu.poke_register_val(*reg, val),
+                        Err(_) => return Ok(Reply::Packet("E01".into())),
+                    }
+                }
+
+                "OK".into()
+            },
+            Some(b'p') => match parse_register(&packet[1..]) {
+                Some(reg) => format!("{:04x}", self.cpu.get_register_val(reg)),
+                None => "E01".into(),
+            },
+            Some(b'P') => {
+                let mut parts = packet[1..].splitn(2, '=');
+                let reg = parts.next().and_then(parse_register);
+                let val = parts.next().and_then(|val| u16::from_str_radix(val, 16).ok());
+
+                match (reg, val) {
+                    (Some(reg), Some(val)) => {
+                        self.cpu.poke_register_val(reg, val);
+                        "OK".into()
+                    },
+                    _ => "E01".into(),
+                }
+            },
+            Some(b'm') => match parse_addr_len(&packet[1..]) {
+                Some((addr, len)) if self.is_mapped(addr, len) => (0..len)
+                    .map(|offset| format!("{:02x}", self.cpu.get_u8(addr.wrapping_add(offset))))
+                    .collect(),
+                _ => "E01".into(),
+            },
+            Some(b'M') => {
+                let mut parts = packet[1..].splitn(2, ':');
+                let target = parts.next().and_then(parse_addr_len);
+                let data = parts.next().and_then(parse_hex_bytes);
+
+                match (target, data) {
+                    (Some((addr, len)), Some(data)) if data.len() == len as usize && self.is_mapped(addr, len) => {
+                        for (offset, byte) in data.into_iter().enumerate() {
+                            self.cpu.set_u8(addr.wrapping_add(offset as Addr), byte);
+                        }
+
+                        "OK".into()
+                    },
+                    _ => "E01".into(),
+                }
+            },
+            Some(b'c') => {
+                self.jump_to(&packet[1..]);
+                let reason = self.resume()?;
+                self.stop_reply(reason)
+            },
+            Some(b's') => {
+                self.jump_to(&packet[1..]);
+                let reason = self.cpu.run_steps(Some(1), true);
+                self.stop_reply(reason)
+            },
+            Some(b'Z') | Some(b'z') => self.toggle_point(packet),
+            Some(b'H') => "OK".into(),
+            Some(b'T') => "OK".into(),
+            Some(b'k') => {
+                self.killed = true;
+                return Ok(Reply::Close(None));
+            },
+            Some(b'D') => return Ok(Reply::Close(Some("OK".into()))),
+            Some(b'q') => self.query(&packet[1..]),
+            Some(b'v') => {
+                if packet == "vCont?" {
+                    "vCont;c;s".into()
+                } else if packet.starts_with("vCont;c") {
+                    let reason = self.resume()?;
+                    self.stop_reply(reason)
+                } else if packet.starts_with("vCont;s") {
+                    let reason = self.cpu.run_steps(Some(1), true);
+                    self.stop_reply(reason)
+                } else {
+                    String::new()
+                }
+            },
+            _ => String::new(),
+        };
+
+        Ok(Reply::Packet(reply))
+    }
+
+    fn query(&mut self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            "PacketSize=1000;qXfer:features:read+;swbreak+".into()
+        } else if query == "Attached" {
+            "1".into()
+        } else if query == "C" {
+            "QC1".into()
+        } else if query == "fThreadInfo" {
+            "m1".into()
+        } else if query == "sThreadInfo" {
+            "l".into()
+        } else if let Some(annex) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let (offset, len) = match parse_addr_len(annex) {
+                Some((offset, len)) => (offset as usize, len as usize),
+                None => return "E01".into(),
+            };
+
+            if offset >= TARGET_XML.len() {
+                return "l".into();
+            }
+
+            let end = (offset + len).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+
+            format!("{}{}", marker, &TARGET_XML[offset..end])
+        } else {
+            String::new()
+        }
+    }
+
+    fn toggle_point(&mut self, packet: &str) -> String {
+        let insert = packet.starts_with('Z');
+
+        let mut parts = packet[1..].split(',');
+        let kind = parts.next();
+        let addr = parts.next().and_then(|addr| u16::from_str_radix(addr, 16).ok());
+        let len = parts.next()
+            .and_then(|len| u16::from_str_radix(len, 16).ok())
+            .unwrap_or(1)
+            .max(1);
+
+        let addr = match addr {
+            Some(addr) => addr,
+            None => return "E01".into(),
+        };
+
+        let watch = match kind {
+            Some("0") | Some("1") => {
+                let debugger = self.cpu.debugger_mut();
+                if insert {
+                    debugger.add_breakpoint(addr);
+                } else {
+                    debugger.remove_breakpoint(addr);
+                }
+
+                return "OK".into();
+            },
+            Some("2") => WatchKind::Write,
+            Some("3") => WatchKind::Read,
+            Some("4") => WatchKind::ReadWrite,
+            _ => return String::new(),
+        };
+
+        let range = addr..=addr.saturating_add(len - 1);
+        let debugger = self.cpu.debugger_mut();
+        if insert {
+            debugger.add_watchpoint(range, watch);
+        } else {
+            debugger.remove_watchpoint(range, watch);
+        }
+
+        "OK".into()
+    }
+
+    fn is_mapped(&self, addr: Addr, len: Addr) -> bool {
+        (0..len).all(|offset| self.cpu.is_mapped(addr.wrapping_add(offset)))
+    }
+
+    fn jump_to(&mut self, addr: &str) {
+        if let Ok(addr) = u16::from_str_radix(addr, 16) {
+            self.cpu.poke_register_val(RegisterVariant::Ip, addr);
+        }
+    }
+
+    /// Runs until the program stops on its own or the debugger sends `^C`,
+    /// in which case the result is `None`.
+    fn resume(&mut self) -> io::Result<Option<StopReason>> {
+        let mut resume = true;
+
+        loop {
+            if let Some(reason) = self.cpu.run_steps(Some(POLL_INTERVAL), resume) {
+                return Ok(Some(reason));
+            }
+
+            resume = false;
+
+            if self.interrupted()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+
+        let mut buf = [0; 1];
+        let res = match self.stream.read(&mut buf) {
+            Ok(1) => Ok(buf[0] == 0x03),
+            Ok(_) => Ok(false),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        };
+
+        self.stream.set_nonblocking(false)?;
+        res
+    }
+
+    fn stop_reply(&mut self, reason: Option<StopReason>) -> String {
+        match reason {
+            Some(StopReason::Halt) => {
+                self.halted = true;
+                "W00".into()
+            },
+            Some(StopReason::Breakpoint(_)) => "T05swbreak:;".into(),
+            Some(StopReason::Watch { addr, kind, .. }) => {
+                let kind = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::ReadWrite => "awatch",
+                };
+
+                format!("T05{}:{:04x};", kind, addr)
+            },
+            Some(StopReason::BudgetExhausted) => "S05".into(),
+            // SIGBUS
+            Some(StopReason::Fault(_)) => "S07".into(),
+            // single steps that didn't stop on their own report a trap too
+            None if !self.halted => "S05".into(),
+            None => "W00".into(),
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn parse_register(reg: &str) -> Option<RegisterVariant> {
+    let reg = u8::from_str_radix(reg, 16).ok()?;
+    if reg > constants::FP {
+        return None;
+    }
+
+    Some(RegisterVariant::from(reg))
+}
+
+fn parse_addr_len(input: &str) -> Option<(Addr, Addr)> {
+    let mut parts = input.splitn(2, ',');
+    let addr = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let len = u16::from_str_radix(parts.next()?, 16).ok()?;
+
+    Some((addr, len))
+}
+
+fn parse_hex_bytes(input: &str) -> Option<Vec<Byte>> {
+    if !input.is_ascii() || !input.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..input.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&input[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use crate::registers::constants::*;
+
+    struct Client(TcpStream);
+
+    impl Client {
+        fn send(&mut self, data: &str) -> String {
+            let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+            self.0.write_all(packet.as_bytes()).unwrap();
+
+            let mut ack = [0; 1];
+            self.0.read_exact(&mut ack).unwrap();
+            assert_eq!(ack[0], b'+');
+
+            let mut reply = Vec::new();
+            let mut byte = [0; 1];
+
+            self.0.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], b'$');
+
+            loop {
+                self.0.read_exact(&mut byte).unwrap();
+                if byte[0] == b'#' {
+                    break;
+                }
+
+                reply.push(byte[0]);
+            }
+
+            let mut checksum = [0; 2];
+            self.0.read_exact(&mut checksum).unwrap();
+            self.0.write_all(b"+").unwrap();
+
+            String::from_utf8(reply).unwrap()
+        }
+    }
+
+    #[test]
+    fn can_debug_over_tcp() {
+        let server = GdbServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let mut memory = Memory::with_capacity(0x100);
+
+            // move lit (0x1234) reg (r1)
+            memory.set_u8(0x0000, MOV_LIT_REG);
+            memory.set_u8(0x0001, 0x12);
+            memory.set_u8(0x0002, 0x34);
+            memory.set_u8(0x0003, R1);
+
+            // inc reg (r1)
+            memory.set_u8(0x0004, INC_REG);
+            memory.set_u8(0x0005, R1);
+
+            memory.set_u8(0x0006, HLT);
+
+            let mut cpu = Cpu::from(memory);
+            server.serve(&mut cpu).unwrap()
+        });
+
+        let mut client = Client(TcpStream::connect(addr).unwrap());
+
+        assert!(client.send("qSupported:swbreak+").contains("qXfer:features:read+"));
+        assert!(client.send("qXfer:features:read:target.xml:0,1000").starts_with("l<?xml"));
+        assert_eq!(client.send("?"), "S05");
+
+        assert_eq!(client.send("m0,4"), "10123402");
+
+        // past the end of the memory, and past the end of the region
+        assert_eq!(client.send("mfe,4"), "E01");
+        assert_eq!(client.send("M100,1:aa"), "E01");
+        assert_eq!(client.send("m1000,1"), "E01");
+
+        assert_eq!(client.send("Z0,4,1"), "OK");
+        assert_eq!(client.send("c"), "T05swbreak:;");
+        assert_eq!(client.send("p2"), "1234");
+
+        assert_eq!(client.send("P2=0041"), "OK");
+        assert_eq!(client.send("s"), "S05");
+        assert_eq!(&client.send("g")[..12], "000600000042");
+
+        assert_eq!(client.send("c"), "W00");
+        assert_eq!(client.send("D"), "OK");
+
+        assert_eq!(handle.join().unwrap(), SessionEnd::Halted);
+    }
+
+    #[test]
+    fn can_kill() {
+        let server = GdbServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let mut cpu = Cpu::from(Memory::with_capacity(0x100));
+            server.serve(&mut cpu).unwrap()
+        });
+
+        let mut client = Client(TcpStream::connect(addr).unwrap());
+        assert_eq!(client.send("?"), "S05");
+
+        // no reply to wait for
+        let packet = format!("$k#{:02x}", checksum_of(b"k"));
+        client.0.write_all(packet.as_bytes()).unwrap();
+
+        assert_eq!(handle.join().unwrap(), SessionEnd::Killed);
+    }
+}
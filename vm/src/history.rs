@@ -0,0 +1,214 @@
+use crate::prelude::*;
+use std::collections::VecDeque;
+
+/// Everything one instruction overwrote, recorded as the values from before
+/// it ran.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HistoryEntry {
+    pub cycles: u64,
+    pub frame_size: Short,
+    pub registers: Vec<(RegisterVariant, Short)>,
+    pub memory: Vec<(Addr, MemoryAccess)>,
+}
+
+/// A bounded log of per-instruction undo records, oldest first. Once full,
+/// recording a new instruction drops the oldest one.
+#[derive(Debug)]
+pub struct History {
+    capacity: usize,
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl History {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn begin(&mut self, frame_size: Short, cycles: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(HistoryEntry {
+            cycles,
+            frame_size,
+            ..HistoryEntry::default()
+        });
+    }
+
+    pub(crate) fn record_register(&mut self, reg: RegisterVariant, old: Short) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.registers.push((reg, old));
+        }
+    }
+
+    pub(crate) fn record_memory(&mut self, addr: Addr, old: MemoryAccess) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.memory.push((addr, old));
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<HistoryEntry> {
+        self.entries.pop_back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use crate::registers::constants::*;
+
+    #[derive(Debug, Default)]
+    struct Latch(Short);
+
+    impl Read for Latch {
+        fn get_u8(&self, _: Addr) -> Byte { self.0 as Byte }
+        fn get_u16(&self, _: Addr) -> Short { self.0 }
+    }
+
+    impl Write for Latch {
+        fn set_u8(&mut self, _: Addr, val: Byte) { self.0 = val as Short; }
+        fn set_u16(&mut self, _: Addr, val: Short) { self.0 = val; }
+    }
+
+    impl Device for Latch {}
+
+    #[test]
+    fn can_step_back() {
+        let bytes = include_bytes!("../tests/binary2");
+
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(bytes);
+
+        let mut cpu = Cpu::from(memory);
+
+        cpu.step();
+        cpu.step();
+
+        let saved = cpu.snapshot();
+
+        cpu.enable_history(0x100);
+        cpu.run();
+
+        assert!(!cpu.run_back_to(0xffff));
+        assert_eq!(cpu.snapshot(), saved);
+    }
+
+    #[test]
+    fn can_step_back_through_subroutine() {
+        // push lit (0x0000)
+        // call subroutine (0x3000)
+        let mut bytes = vec![
+            PSH_LIT, 0x00, 0x00,
+            CAL_LIT, 0x30, 0x00,
+            HLT,
+        ];
+
+        // subroutine: move lit (0x0708) reg (r1)
+        bytes.resize(0x3000, 0);
+        bytes.extend_from_slice(&[
+            MOV_LIT_REG, 0x07, 0x08, R1,
+            RET,
+        ]);
+
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(&bytes);
+
+        let mut cpu = Cpu::from(memory);
+        cpu.enable_history(0x100);
+
+        cpu.step();
+        let before_call = cpu.snapshot();
+
+        cpu.step();
+        let in_subroutine = cpu.snapshot();
+
+        cpu.run();
+        assert_eq!(cpu.history().unwrap().len(), 5);
+
+        assert!(cpu.run_back_to(0x3000));
+        assert_eq!(cpu.snapshot(), in_subroutine);
+
+        assert!(cpu.step_back());
+        assert_eq!(cpu.snapshot(), before_call);
+    }
+
+    #[test]
+    fn cannot_step_back_devices() {
+        let mut memory = Memory::with_capacity(0x10000);
+
+        // move lit (0x1111) mem (0x3000), to the latch
+        // move lit (0x2222) mem (0x2000)
+        memory.set_bytes(&[
+            MOV_LIT_MEM, 0x11, 0x11, 0x30, 0x00,
+            MOV_LIT_MEM, 0x22, 0x22, 0x20, 0x00,
+            HLT,
+        ]);
+
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x3000..=0x3001)
+                .priority(1)
+                .device(Box::new(Latch(0x5555)))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(memory.get_range())
+                .memory(memory)
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        let mut cpu = Cpu::from(mm);
+        cpu.enable_history(0x100);
+        cpu.run();
+
+        assert!(cpu.run_back_to(0x0000));
+        assert_eq!(cpu.get_u16(0x2000), 0x0000);
+        assert_eq!(cpu.get_u16(0x3000), 0x1111);
+    }
+
+    #[test]
+    fn cannot_step_back_past_restore() {
+        let bytes = include_bytes!("../tests/binary2");
+
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(bytes);
+
+        let mut cpu = Cpu::from(memory);
+        cpu.enable_history(0x100);
+
+        let saved = cpu.snapshot();
+        cpu.run();
+
+        cpu.restore(&saved).unwrap();
+        assert!(!cpu.step_back());
+        assert_eq!(cpu.history().unwrap().capacity(), 0x100);
+        assert_eq!(cpu.snapshot(), saved);
+    }
+}
@@ -55,6 +55,59 @@ pub enum InstructionVariant {
 }
 
 impl InstructionVariant {
+    pub const VARIANTS: [InstructionVariant; 46] = [
+        Self::MoveLitReg,
+        Self::MoveRegReg,
+        Self::MoveRegMem,
+        Self::MoveMemReg,
+        Self::MoveLitMem,
+        Self::MoveRegPtrReg,
+        Self::MoveLitOffReg,
+
+        Self::AddRegReg,
+        Self::AddLitReg,
+        Self::SubLitReg,
+        Self::SubRegLit,
+        Self::SubRegReg,
+        Self::IncReg,
+        Self::DecReg,
+        Self::MulLitReg,
+        Self::MulRegReg,
+
+        Self::LeftShiftRegLit,
+        Self::LeftShiftRegReg,
+        Self::RightShiftRegLit,
+        Self::RightShiftRegReg,
+        Self::AndRegLit,
+        Self::AndRegReg,
+        Self::OrRegLit,
+        Self::OrRegReg,
+        Self::XorRegLit,
+        Self::XorRegReg,
+        Self::Not,
+
+        Self::JumpNotEqReg,
+        Self::JumpNotEqLit,
+        Self::JumpEqReg,
+        Self::JumpEqLit,
+        Self::JumpLtReg,
+        Self::JumpLtLit,
+        Self::JumpGtReg,
+        Self::JumpGtLit,
+        Self::JumpLteReg,
+        Self::JumpLteLit,
+        Self::JumpGteReg,
+        Self::JumpGteLit,
+
+        Self::PushLit,
+        Self::PushReg,
+        Self::Pop,
+        Self::CallLit,
+        Self::CallReg,
+        Self::Ret,
+        Self::Halt,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Self::MoveLitReg => "mov",
@@ -169,59 +222,59 @@ impl From<Byte> for InstructionVariant {
     }
 }
 
-impl Into<Byte> for InstructionVariant {
-    fn into(self) -> Byte {
-        match self {
-            Self::MoveLitReg => constants::MOV_LIT_REG,
-            Self::MoveRegReg => constants::MOV_REG_REG,
-            Self::MoveRegMem => constants::MOV_REG_MEM,
-            Self::MoveMemReg => constants::MOV_MEM_REG,
-            Self::MoveLitMem => constants::MOV_LIT_MEM,
-            Self::MoveRegPtrReg => constants::MOV_REG_PTR_REG,
-            Self::MoveLitOffReg => constants::MOV_LIT_OFF_REG,
-
-            Self::AddRegReg => constants::ADD_REG_REG,
-            Self::AddLitReg => constants::ADD_LIT_REG,
-            Self::SubLitReg => constants::SUB_LIT_REG,
-            Self::SubRegLit => constants::SUB_REG_LIT,
-            Self::SubRegReg => constants::SUB_REG_REG,
-            Self::IncReg => constants::INC_REG,
-            Self::DecReg => constants::DEC_REG,
-            Self::MulLitReg => constants::MUL_LIT_REG,
-            Self::MulRegReg => constants::MUL_REG_REG,
-
-            Self::LeftShiftRegLit => constants::LSF_REG_LIT,
-            Self::LeftShiftRegReg => constants::LSF_REG_REG,
-            Self::RightShiftRegLit => constants::RSF_REG_LIT,
-            Self::RightShiftRegReg => constants::RSF_REG_REG,
-            Self::AndRegLit => constants::AND_REG_LIT,
-            Self::AndRegReg => constants::AND_REG_REG,
-            Self::OrRegLit => constants::OR_REG_LIT,
-            Self::OrRegReg => constants::OR_REG_REG,
-            Self::XorRegLit => constants::XOR_REG_LIT,
-            Self::XorRegReg => constants::XOR_REG_REG,
-            Self::Not => constants::NOT,
-
-            Self::JumpNotEqReg => constants::JNE_REG,
-            Self::JumpNotEqLit => constants::JNE_LIT,
-            Self::JumpEqReg => constants::JEQ_REG,
-            Self::JumpEqLit => constants::JEQ_LIT,
-            Self::JumpLtReg => constants::JLT_REG,
-            Self::JumpLtLit => constants::JLT_LIT,
-            Self::JumpGtReg => constants::JGT_REG,
-            Self::JumpGtLit => constants::JGT_LIT,
-            Self::JumpLteReg => constants::JLE_REG,
-            Self::JumpLteLit => constants::JLE_LIT,
-            Self::JumpGteReg => constants::JGE_REG,
-            Self::JumpGteLit => constants::JGE_LIT,
-
-            Self::PushLit => constants::PSH_LIT,
-            Self::PushReg => constants::PSH_REG,
-            Self::Pop => constants::POP,
-            Self::CallLit => constants::CAL_LIT,
-            Self::CallReg => constants::CAL_REG,
-            Self::Ret => constants::RET,
-            Self::Halt => constants::HLT,
+impl From<InstructionVariant> for Byte {
+    fn from(val: InstructionVariant) -> Self {
+        match val {
+            InstructionVariant::MoveLitReg => constants::MOV_LIT_REG,
+            InstructionVariant::MoveRegReg => constants::MOV_REG_REG,
+            InstructionVariant::MoveRegMem => constants::MOV_REG_MEM,
+            InstructionVariant::MoveMemReg => constants::MOV_MEM_REG,
+            InstructionVariant::MoveLitMem => constants::MOV_LIT_MEM,
+            InstructionVariant::MoveRegPtrReg => constants::MOV_REG_PTR_REG,
+            InstructionVariant::MoveLitOffReg => constants::MOV_LIT_OFF_REG,
+
+            InstructionVariant::AddRegReg => constants::ADD_REG_REG,
+            InstructionVariant::AddLitReg => constants::ADD_LIT_REG,
+            InstructionVariant::SubLitReg => constants::SUB_LIT_REG,
+            InstructionVariant::SubRegLit => constants::SUB_REG_LIT,
+            InstructionVariant::SubRegReg => constants::SUB_REG_REG,
+            InstructionVariant::IncReg => constants::INC_REG,
+            InstructionVariant::DecReg => constants::DEC_REG,
+            InstructionVariant::MulLitReg => constants::MUL_LIT_REG,
+            InstructionVariant::MulRegReg => constants::MUL_REG_REG,
+
+            InstructionVariant::LeftShiftRegLit => constants::LSF_REG_LIT,
+            InstructionVariant::LeftShiftRegReg => constants::LSF_REG_REG,
+            InstructionVariant::RightShiftRegLit => constants::RSF_REG_LIT,
+            InstructionVariant::RightShiftRegReg => constants::RSF_REG_REG,
+            InstructionVariant::AndRegLit => constants::AND_REG_LIT,
+            InstructionVariant::AndRegReg => constants::AND_REG_REG,
+            InstructionVariant::OrRegLit => constants::OR_REG_LIT,
+            InstructionVariant::OrRegReg => constants::OR_REG_REG,
+            InstructionVariant::XorRegLit => constants::XOR_REG_LIT,
+            InstructionVariant::XorRegReg => constants::XOR_REG_REG,
+            InstructionVariant::Not => constants::NOT,
+
+            InstructionVariant::JumpNotEqReg => constants::JNE_REG,
+            InstructionVariant::JumpNotEqLit => constants::JNE_LIT,
+            InstructionVariant::JumpEqReg => constants::JEQ_REG,
+            InstructionVariant::JumpEqLit => constants::JEQ_LIT,
+            InstructionVariant::JumpLtReg => constants::JLT_REG,
+            InstructionVariant::JumpLtLit => constants::JLT_LIT,
+            InstructionVariant::JumpGtReg => constants::JGT_REG,
+            InstructionVariant::JumpGtLit => constants::JGT_LIT,
+            InstructionVariant::JumpLteReg => constants::JLE_REG,
+            InstructionVariant::JumpLteLit => constants::JLE_LIT,
+            InstructionVariant::JumpGteReg => constants::JGE_REG,
+            InstructionVariant::JumpGteLit => constants::JGE_LIT,
+
+            InstructionVariant::PushLit => constants::PSH_LIT,
+            InstructionVariant::PushReg => constants::PSH_REG,
+            InstructionVariant::Pop => constants::POP,
+            InstructionVariant::CallLit => constants::CAL_LIT,
+            InstructionVariant::CallReg => constants::CAL_REG,
+            InstructionVariant::Ret => constants::RET,
+            InstructionVariant::Halt => constants::HLT,
         }
     }
 }
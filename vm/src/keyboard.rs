@@ -0,0 +1,339 @@
+use crate::prelude::*;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+const KEY_AVAILABLE: Short = 0b01;
+const INTERRUPTS: Short = 0b10;
+
+/// A memory-mapped keyboard, four bytes wide. Keys are queued as they
+/// arrive, and with interrupts enabled every new key interrupts the program.
+#[derive(Debug)]
+pub struct Keyboard {
+    input: Option<Receiver<Byte>>,
+    interrupts: bool,
+    pending: bool,
+    queue: VecDeque<Byte>,
+    #[cfg(unix)]
+    raw: Option<raw::RawMode>,
+}
+
+impl Keyboard {
+    /// Bit 0: a key is waiting in `DATA`. Bit 1: interrupts are enabled;
+    /// write the bit to change it.
+    pub const STATUS: Addr = 0x0000;
+
+    /// The oldest key not yet acknowledged, or zero. Reading doesn't consume
+    /// it; writing anything does.
+    pub const DATA: Addr = 0x0002;
+
+    /// How many addresses the device takes up.
+    pub fn len(&self) -> usize {
+        Self::DATA as usize + 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn new(input: Receiver<Byte>) -> Self {
+        Self {
+            input: Some(input),
+            interrupts: false,
+            pending: false,
+            queue: VecDeque::new(),
+            #[cfg(unix)]
+            raw: None,
+        }
+    }
+
+    /// Reads keys from `reader` on a background thread.
+    pub fn from_reader<R: std::io::Read + Send + 'static>(reader: R) -> Self {
+        Self::new(spawn_reader(reader))
+    }
+
+    /// Reads keys from stdin. If stdin is a terminal it's put in raw mode,
+    /// so keys arrive as they're pressed rather than a line at a time, until
+    /// the keyboard is dropped.
+    pub fn stdin() -> Self {
+        #[allow(unused_mut)]
+        let mut keyboard = Self::from_reader(std::io::stdin());
+
+        #[cfg(unix)]
+        {
+            keyboard.raw = raw::RawMode::enable();
+        }
+
+        keyboard
+    }
+
+    /// Keys received but not yet acknowledged by the program.
+    pub fn queue(&self) -> &VecDeque<Byte> {
+        &self.queue
+    }
+
+    fn receive(&mut self) {
+        let input = match self.input.as_ref() {
+            Some(input) => input,
+            None => return,
+        };
+
+        loop {
+            match input.try_recv() {
+                Ok(key) => {
+                    self.queue.push_back(key);
+                    self.pending |= self.interrupts;
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.input = None;
+                    break;
+                },
+            }
+        }
+    }
+
+    fn status(&self) -> Short {
+        let mut status = 0;
+
+        if !self.queue.is_empty() {
+            status |= KEY_AVAILABLE;
+        }
+
+        if self.interrupts {
+            status |= INTERRUPTS;
+        }
+
+        status
+    }
+}
+
+/// Sends every byte read from `reader` down a channel, from a background
+/// thread, until it runs out or the receiver is dropped.
+pub(crate) fn spawn_reader<R: std::io::Read + Send + 'static>(mut reader: R) -> Receiver<Byte> {
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let mut buf = [0; 64];
+
+        loop {
+            let len = match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(len) => len,
+            };
+
+            if buf[..len].iter().any(|byte| sender.send(*byte).is_err()) {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+impl Read for Keyboard {
+    fn get_u8(&self, addr: Addr) -> Byte {
+        self.get_register_u8(addr)
+    }
+
+    fn get_u16(&self, addr: Addr) -> Short {
+        match addr {
+            Self::STATUS => self.status(),
+            Self::DATA => self.queue.front().copied().unwrap_or(0) as Short,
+            _ => 0,
+        }
+    }
+}
+
+impl Write for Keyboard {
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        // only the low byte of either register means anything
+        if addr & 1 == 1 {
+            self.set_u16(addr & !1, val as Short);
+        }
+    }
+
+    fn set_u16(&mut self, addr: Addr, val: Short) {
+        match addr {
+            Self::STATUS => self.interrupts = val & INTERRUPTS != 0,
+            Self::DATA => {
+                self.queue.pop_front();
+            },
+            _ => (),
+        }
+    }
+}
+
+impl WordRegisters for Keyboard {}
+
+impl Device for Keyboard {
+    fn tick(&mut self, _cycles: u64) {
+        self.receive();
+    }
+
+    fn take_interrupt(&mut self) -> bool {
+        std::mem::replace(&mut self.pending, false)
+    }
+}
+
+#[cfg(unix)]
+mod raw {
+    /// Turns off line buffering and echo on stdin for as long as it lives.
+    /// Signals like `^C` still work.
+    pub struct RawMode(libc::termios);
+
+    impl RawMode {
+        /// `None` if stdin isn't a terminal.
+        pub fn enable() -> Option<Self> {
+            unsafe {
+                if libc::isatty(libc::STDIN_FILENO) != 1 {
+                    return None;
+                }
+
+                let mut termios = std::mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                    return None;
+                }
+
+                let original = termios;
+
+                termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+                termios.c_cc[libc::VMIN] = 1;
+                termios.c_cc[libc::VTIME] = 0;
+
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                    return None;
+                }
+
+                Some(Self(original))
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0);
+            }
+        }
+    }
+
+    impl std::fmt::Debug for RawMode {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("RawMode")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::STACK_TOP;
+    use crate::instructions::constants::*;
+    use crate::registers::constants::*;
+
+    #[test]
+    fn can_queue_keys() {
+        let (sender, receiver) = mpsc::channel();
+        let mut keyboard = Keyboard::new(receiver);
+
+        assert_eq!(keyboard.get_u16(Keyboard::STATUS), 0);
+
+        sender.send(b'h').unwrap();
+        sender.send(b'i').unwrap();
+        keyboard.tick(1);
+
+        assert_eq!(keyboard.get_u16(Keyboard::STATUS), KEY_AVAILABLE);
+        assert_eq!(keyboard.get_u16(Keyboard::DATA), b'h' as Short);
+        assert_eq!(keyboard.get_u16(Keyboard::DATA), b'h' as Short);
+
+        keyboard.set_u16(Keyboard::DATA, 0);
+        assert_eq!(keyboard.get_u8(Keyboard::DATA + 1), b'i');
+
+        keyboard.set_u8(Keyboard::DATA + 1, 0);
+        assert_eq!(keyboard.get_u16(Keyboard::STATUS), 0);
+        assert_eq!(keyboard.get_u16(Keyboard::DATA), 0);
+    }
+
+    #[test]
+    fn can_interrupt() {
+        let (sender, receiver) = mpsc::channel();
+        let mut keyboard = Keyboard::new(receiver);
+
+        sender.send(b'a').unwrap();
+        keyboard.tick(1);
+        assert!(!keyboard.take_interrupt());
+
+        keyboard.set_u16(Keyboard::STATUS, INTERRUPTS);
+        assert_eq!(keyboard.get_u16(Keyboard::STATUS), KEY_AVAILABLE | INTERRUPTS);
+
+        sender.send(b'b').unwrap();
+        keyboard.tick(1);
+        assert!(keyboard.take_interrupt());
+        assert!(!keyboard.take_interrupt());
+    }
+
+    #[test]
+    fn can_interrupt_cpu() {
+        // move lit (0x0002) mem (keyboard status), to enable interrupts
+        // move lit (0x1234) reg (r1)
+        // loop: jeq lit (0x0000) addr (loop)
+        let mut bytes = vec![
+            MOV_LIT_MEM, 0x00, 0x02, 0x30, 0x00,
+            MOV_LIT_REG, 0x12, 0x34, R1,
+            JEQ_LIT, 0x00, 0x00, 0x00, 0x09,
+            HLT,
+        ];
+
+        // handler: move mem (keyboard data) reg (acc)
+        // move lit (0x0000) mem (keyboard data), to take the key
+        // move lit (0x0000) reg (r1), clobbered but restored on return
+        bytes.resize(0x0100, 0);
+        bytes.extend_from_slice(&[
+            MOV_MEM_REG, 0x30, 0x02, ACC,
+            MOV_LIT_MEM, 0x00, 0x00, 0x30, 0x02,
+            MOV_LIT_REG, 0x00, 0x00, R1,
+            RET,
+        ]);
+
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(&bytes);
+
+        let (sender, receiver) = mpsc::channel();
+
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x3000..=0x3003)
+                .priority(1)
+                .device(Box::new(Keyboard::new(receiver)))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(memory.get_range())
+                .memory(memory)
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        let mut cpu = Cpu::from(mm);
+
+        // no handler, so the key is queued but nothing happens
+        sender.send(b'q').unwrap();
+        assert_eq!(cpu.run_for(20), StopReason::BudgetExhausted);
+        assert_eq!(cpu.register(RegisterVariant::Acc), 0x0000);
+
+        cpu.set_interrupt_handler(Some(0x0100));
+        sender.send(b'!').unwrap();
+        assert_eq!(cpu.run_for(20), StopReason::Halt);
+
+        // only the second key interrupted, but the first was still queued
+        assert_eq!(cpu.register(RegisterVariant::Acc), b'q' as Short);
+        assert_eq!(cpu.register(RegisterVariant::R1), 0x1234);
+        assert_eq!(cpu.register(RegisterVariant::Sp), STACK_TOP);
+        assert_eq!(cpu.get_u16(0x3002), b'!' as Short);
+    }
+}
@@ -1,11 +1,35 @@
+mod banked_memory;
+mod block_device;
+mod coverage;
 mod cpu;
+mod debugger;
+mod decode;
+mod dma;
+mod fault;
+mod framebuffer;
+pub mod gdb;
+mod history;
 pub mod instructions;
+mod keyboard;
 mod memory;
+mod observer;
+mod pixel_display;
+mod profiler;
 pub mod registers;
+mod rng;
+mod rtc;
 mod screen_device;
+mod snapshot;
+mod timing;
+mod uart;
 
 mod traits {
+    use crate::fault::DeviceFault;
+    use crate::memory::Bus;
+    use crate::snapshot::SnapshotError;
     use crate::types::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
 
     pub trait Read {
         fn get_u8(&self, addr: Addr) -> Byte;
@@ -17,7 +41,81 @@ mod traits {
         fn set_u16(&mut self, addr: Addr, val: Short);
     }
 
-    pub trait Device: Read + Write + std::fmt::Debug {}
+    /// For devices whose registers are `Short`s at even addresses. A byte
+    /// access reaches half of a register: the high byte at the even address,
+    /// the low byte after it.
+    pub trait WordRegisters: Read + Write {
+        fn get_register_u8(&self, addr: Addr) -> Byte {
+            let val = self.get_u16(addr & !1);
+
+            if addr & 1 == 0 { (val >> 8) as Byte } else { val as Byte }
+        }
+
+        /// Replaces one half of the register, keeping the other.
+        fn set_register_u8(&mut self, addr: Addr, val: Byte) {
+            let old = self.get_u16(addr & !1);
+            let new = if addr & 1 == 0 {
+                (old & 0x00ff) | (val as Short) << 8
+            } else {
+                (old & 0xff00) | val as Short
+            };
+
+            self.set_u16(addr & !1, new);
+        }
+    }
+
+    pub trait Device: Read + Write + std::fmt::Debug {
+        /// State to store in a `Snapshot`. Devices with nothing worth
+        /// restoring can keep the default.
+        fn save_state(&self) -> Vec<Byte> { Vec::new() }
+
+        fn load_state(&mut self, _state: &[Byte]) -> Result<(), SnapshotError> { Ok(()) }
+
+        /// Whether the `Cpu` may cache instructions decoded from this device.
+        /// Only devices whose contents change solely through `Write` should
+        /// say yes.
+        fn cacheable(&self) -> bool { false }
+
+        /// Called after every instruction with the cycles it took, for
+        /// devices that keep time.
+        fn tick(&mut self, _cycles: u64) {}
+
+        /// Polled after every instruction. A device that wants to interrupt
+        /// the program returns `true`, once per interrupt.
+        fn take_interrupt(&mut self) -> bool { false }
+
+        /// Polled after every instruction. A device the program misused
+        /// returns why, once, and the `Cpu` stops.
+        fn take_fault(&mut self) -> Option<DeviceFault> { None }
+
+        /// Called after every instruction, for devices that read or write
+        /// memory themselves. Writes made here count as part of the
+        /// instruction that just ran: watchpoints, observers and
+        /// `Cpu::step_back` see them like the program's own.
+        fn access_bus(&mut self, _bus: &mut Bus<'_>) {}
+    }
+
+    // lets a device be inspected after it's handed to a `MemoryRegion`, by
+    // keeping a clone of the `Rc`
+    impl<D: Read> Read for Rc<RefCell<D>> {
+        fn get_u8(&self, addr: Addr) -> Byte { self.borrow().get_u8(addr) }
+        fn get_u16(&self, addr: Addr) -> Short { self.borrow().get_u16(addr) }
+    }
+
+    impl<D: Write> Write for Rc<RefCell<D>> {
+        fn set_u8(&mut self, addr: Addr, val: Byte) { self.borrow_mut().set_u8(addr, val); }
+        fn set_u16(&mut self, addr: Addr, val: Short) { self.borrow_mut().set_u16(addr, val); }
+    }
+
+    impl<D: Device> Device for Rc<RefCell<D>> {
+        fn save_state(&self) -> Vec<Byte> { self.borrow().save_state() }
+        fn load_state(&mut self, state: &[Byte]) -> Result<(), SnapshotError> { self.borrow_mut().load_state(state) }
+        fn cacheable(&self) -> bool { self.borrow().cacheable() }
+        fn tick(&mut self, cycles: u64) { self.borrow_mut().tick(cycles); }
+        fn take_interrupt(&mut self) -> bool { self.borrow_mut().take_interrupt() }
+        fn take_fault(&mut self) -> Option<DeviceFault> { self.borrow_mut().take_fault() }
+        fn access_bus(&mut self, bus: &mut Bus<'_>) { self.borrow_mut().access_bus(bus); }
+    }
 }
 
 mod types {
@@ -27,21 +125,76 @@ mod types {
 }
 
 pub mod prelude {
-    pub use crate::cpu::Cpu;
+    pub use crate::banked_memory::BankedMemory;
+    pub use crate::block_device::{
+        BlockDevice,
+        SECTOR_SIZE,
+    };
+    pub use crate::coverage::{
+        BranchCoverage,
+        Coverage,
+    };
+    pub use crate::cpu::{
+        Cpu,
+        Frame,
+    };
+    pub use crate::debugger::{
+        Debugger,
+        StopReason,
+        WatchKind,
+        Watchpoint,
+    };
+    pub use crate::dma::Dma;
+    pub use crate::fault::DeviceFault;
+    pub use crate::framebuffer::{
+        Attributes,
+        Cell,
+        FramebufferScreen,
+        ScreenCommand,
+    };
+    pub use crate::history::{
+        History,
+        HistoryEntry,
+    };
     pub use crate::instructions::{
         InstructionArguments,
         InstructionVariant,
     };
+    pub use crate::keyboard::Keyboard;
     pub use crate::memory::{
+        Bus,
         Memory,
         MemoryMapper,
+        MemoryMapperError,
         MemoryRegion,
     };
+    pub use crate::observer::{
+        CpuObserver,
+        MemoryAccess,
+    };
+    pub use crate::pixel_display::{
+        write_png,
+        write_ppm,
+        PixelDisplay,
+    };
+    pub use crate::profiler::{
+        Profiler,
+        SubroutineProfile,
+    };
     pub use crate::registers::{
         Register,
         RegisterVariant,
     };
+    pub use crate::rng::Rng;
+    pub use crate::rtc::Rtc;
     pub use crate::traits::*;
     pub use crate::types::*;
     pub use crate::screen_device::*;
+    pub use crate::snapshot::{
+        RegionSnapshot,
+        Snapshot,
+        SnapshotError,
+    };
+    pub use crate::timing::CycleCosts;
+    pub use crate::uart::Uart;
 }
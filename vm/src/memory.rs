@@ -18,7 +18,7 @@ impl Memory {
         0x0000..=self.0.capacity()
     }
 
-    pub fn set_bytes<'a>(&mut self, bytes: &'a [Byte]) {
+    pub fn set_bytes(&mut self, bytes: &[Byte]) {
         for (i, byte) in bytes.iter().enumerate() {
             *self.0.get_mut(i).unwrap() = *byte;
         }
@@ -29,7 +29,7 @@ impl Read for Memory {
     fn get_u8(&self, addr: Addr) -> Byte {
         let addr = addr as usize;
         let entry = self.0.get(addr)
-            .expect(&format!("address {:#x?} out of bounds", addr));
+            .unwrap_or_else(|| panic!("address {:#x?} out of bounds", addr));
 
         *entry
     }
@@ -38,9 +38,9 @@ impl Read for Memory {
         let addr = addr as usize;
 
         let entry_upper = self.0.get(addr)
-            .expect(&format!("address {:#x?} out of bounds", addr));
+            .unwrap_or_else(|| panic!("address {:#x?} out of bounds", addr));
         let entry_lower = self.0.get(addr + 1)
-            .expect(&format!("address {:#x?} out of bounds", addr));
+            .unwrap_or_else(|| panic!("address {:#x?} out of bounds", addr));
 
         (*entry_upper as Short).checked_shl(0b1000).expect("cannot left shift") + *entry_lower as Short
     }
@@ -50,7 +50,7 @@ impl Write for Memory {
     fn set_u8(&mut self, addr: Addr, val: Byte) {
         let addr = addr as usize;
         let entry =  self.0.get_mut(addr)
-            .expect(&format!("address {:#x?} out of bounds", addr));
+            .unwrap_or_else(|| panic!("address {:#x?} out of bounds", addr));
 
         *entry = val;
     }
@@ -60,26 +60,58 @@ impl Write for Memory {
 
         {
             let entry_upper = self.0.get_mut(addr)
-                .expect(&format!("address {:#x?} out of bounds", addr));
+                .unwrap_or_else(|| panic!("address {:#x?} out of bounds", addr));
 
             *entry_upper = val.checked_shr(0b1000).expect("cannot shift right") as Byte;
         }
 
         {
             let entry_lower = self.0.get_mut(addr + 1)
-                .expect(&format!("address {:#x?} out of bounds", addr));
+                .unwrap_or_else(|| panic!("address {:#x?} out of bounds", addr));
 
             *entry_lower = val as Byte;
         }
     }
 }
 
-impl Device for Memory {}
+impl Device for Memory {
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    fn save_state(&self) -> Vec<Byte> {
+        self.0.clone()
+    }
+
+    fn load_state(&mut self, state: &[Byte]) -> Result<(), SnapshotError> {
+        if state.len() != self.0.len() {
+            return Err(SnapshotError::new(format!(
+                "expected {:#x?} bytes of memory, found {:#x?}",
+                self.0.len(),
+                state.len(),
+            )));
+        }
+
+        self.0.copy_from_slice(state);
+        Ok(())
+    }
+}
+
+/// What a region is backed by. Plain RAM is kept unboxed so the common case
+/// of reading and writing memory doesn't go through `dyn Device`.
+#[derive(Debug)]
+enum Backing {
+    Memory(Memory),
+    Device(Box<dyn Device>),
+}
 
 #[derive(Debug)]
 pub struct MemoryRegion {
-    pub device: Box<dyn Device>,
+    access_cycles: u64,
+    backing: Backing,
+    priority: u8,
     range: RangeInclusive<usize>,
+    read_only: bool,
     should_remap: bool,
 }
 
@@ -87,19 +119,126 @@ impl MemoryRegion {
     pub fn builder() -> MemoryRegionBuilder {
         MemoryRegionBuilder::default()
     }
+
+    /// Extra cycles every program read or write of this region costs.
+    pub fn access_cycles(&self) -> u64 {
+        self.access_cycles
+    }
+
+    pub fn device(&self) -> &dyn Device {
+        match &self.backing {
+            Backing::Memory(memory) => memory,
+            Backing::Device(device) => device.as_ref(),
+        }
+    }
+
+    pub fn device_mut(&mut self) -> &mut dyn Device {
+        match &mut self.backing {
+            Backing::Memory(memory) => memory,
+            Backing::Device(device) => device.as_mut(),
+        }
+    }
+
+    /// Where overlapping regions are allowed, the one with the higher
+    /// priority handles the overlap.
+    pub fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    pub fn range(&self) -> &RangeInclusive<usize> {
+        &self.range
+    }
+
+    /// Whether the program is kept from writing to the region. A write
+    /// stops it with a fault instead; writes from outside the program, like
+    /// loading it or a debugger patching it, still go through.
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Whether the device sees addresses relative to the start of the
+    /// region rather than the address the program used.
+    pub fn should_remap(&self) -> bool {
+        self.should_remap
+    }
+
+    /// Whether the region is plain RAM, which can be read without side
+    /// effects.
+    pub fn is_memory(&self) -> bool {
+        matches!(self.backing, Backing::Memory(_))
+    }
+
+    fn local(&self, addr: Addr) -> Addr {
+        if self.should_remap {
+            addr - *self.range.start() as Addr
+        } else {
+            addr
+        }
+    }
+
+    /// Whether `addr` is backed by something: plain memory can be shorter
+    /// than the region it's mapped into.
+    fn reaches(&self, addr: Addr) -> bool {
+        match &self.backing {
+            Backing::Memory(memory) => (self.local(addr) as usize) < memory.0.len(),
+            Backing::Device(_) => true,
+        }
+    }
+
+    fn overlaps(&self, other: &MemoryRegion) -> bool {
+        self.range.start() <= other.range.end() && other.range.start() <= self.range.end()
+    }
 }
 
 impl Read for MemoryRegion {
-    fn get_u8(&self, addr: Addr) -> Byte { self.device.get_u8(addr) }
-    fn get_u16(&self, addr: Addr) -> Short { self.device.get_u16(addr) }
+    fn get_u8(&self, addr: Addr) -> Byte {
+        let addr = self.local(addr);
+
+        match &self.backing {
+            Backing::Memory(memory) => memory.get_u8(addr),
+            Backing::Device(device) => device.get_u8(addr),
+        }
+    }
+
+    fn get_u16(&self, addr: Addr) -> Short {
+        let addr = self.local(addr);
+
+        match &self.backing {
+            Backing::Memory(memory) => memory.get_u16(addr),
+            Backing::Device(device) => device.get_u16(addr),
+        }
+    }
 }
 
 impl Write for MemoryRegion {
-    fn set_u8(&mut self, addr: Addr, val: Byte) { self.device.set_u8(addr, val); }
-    fn set_u16(&mut self, addr: Addr, val: Short) { self.device.set_u16(addr, val); }
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        let addr = self.local(addr);
+
+        match &mut self.backing {
+            Backing::Memory(memory) => memory.set_u8(addr, val),
+            Backing::Device(device) => device.set_u8(addr, val),
+        }
+    }
+
+    fn set_u16(&mut self, addr: Addr, val: Short) {
+        let addr = self.local(addr);
+
+        match &mut self.backing {
+            Backing::Memory(memory) => memory.set_u16(addr, val),
+            Backing::Device(device) => device.set_u16(addr, val),
+        }
+    }
 }
 
-impl Device for MemoryRegion {}
+impl Device for MemoryRegion {
+    fn save_state(&self) -> Vec<Byte> { self.device().save_state() }
+    fn load_state(&mut self, state: &[Byte]) -> Result<(), SnapshotError> { self.device_mut().load_state(state) }
+    fn tick(&mut self, cycles: u64) { self.device_mut().tick(cycles); }
+    fn cacheable(&self) -> bool { self.device().cacheable() }
+    fn take_interrupt(&mut self) -> bool { self.device_mut().take_interrupt() }
+    fn take_fault(&mut self) -> Option<DeviceFault> { self.device_mut().take_fault() }
+    fn access_bus(&mut self, bus: &mut Bus<'_>) { self.device_mut().access_bus(bus); }
+}
 
 #[derive(Debug)]
 pub struct MemoryRegionBuilderError(String);
@@ -111,14 +250,33 @@ impl std::fmt::Display for MemoryRegionBuilderError {
 }
 
 pub struct MemoryRegionBuilder {
-    device: Option<Box<dyn Device>>,
+    access_cycles: u64,
+    backing: Option<Backing>,
+    priority: u8,
     range: Option<RangeInclusive<usize>>,
+    read_only: bool,
     should_remap: bool,
 }
 
 impl MemoryRegionBuilder {
+    pub fn access_cycles(mut self, access_cycles: u64) -> Self {
+        self.access_cycles = access_cycles;
+        self
+    }
+
     pub fn device(mut self, device: Box<dyn Device>) -> Self {
-        self.device = Some(device);
+        self.backing = Some(Backing::Device(device));
+        self
+    }
+
+    /// Backs the region with plain RAM. Same as `device`, but faster.
+    pub fn memory(mut self, memory: Memory) -> Self {
+        self.backing = Some(Backing::Memory(memory));
+        self
+    }
+
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
         self
     }
 
@@ -127,14 +285,23 @@ impl MemoryRegionBuilder {
         self
     }
 
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// On by default: the device sees `0x0000` at the start of the region,
+    /// so the same device works wherever it's mapped. Turned off, the device
+    /// sees the addresses the program used, e.g. for one `Memory` covering
+    /// the whole address space behind smaller regions.
     pub fn should_remap(mut self, should_remap: bool) -> Self {
         self.should_remap = should_remap;
         self
     }
 
     pub fn finalize(self) -> Result<MemoryRegion, MemoryRegionBuilderError> {
-        let device = match self.device {
-            Some(device) => device,
+        let backing = match self.backing {
+            Some(backing) => backing,
             None => return Err(MemoryRegionBuilderError("missing field `device`".into())),
         };
 
@@ -144,8 +311,11 @@ impl MemoryRegionBuilder {
         };
 
         Ok(MemoryRegion {
-            device,
+            access_cycles: self.access_cycles,
+            backing,
+            priority: self.priority,
             range,
+            read_only: self.read_only,
             should_remap: self.should_remap
         })
     }
@@ -154,38 +324,492 @@ impl MemoryRegionBuilder {
 impl Default for MemoryRegionBuilder {
     fn default() -> Self {
         Self {
-            device: None,
+            access_cycles: 0,
+            backing: None,
+            priority: 0,
             range: None,
+            read_only: false,
             should_remap: true,
         }
     }
 }
 
+#[derive(Debug)]
+pub struct MemoryMapperError(String);
+
+impl std::fmt::Display for MemoryMapperError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for MemoryMapperError {}
+
+const PAGE_SIZE: usize = 0x100;
+const PAGES: usize = 0x10000 / PAGE_SIZE;
+
+/// Page table entries that don't name a region.
+const UNMAPPED: u16 = u16::MAX;
+const MIXED: u16 = u16::MAX - 1;
+
+/// Routes addresses to regions. Regions may only overlap if they have
+/// different priorities; the higher priority wins. Lookups go through a
+/// table of 256-byte pages, falling back to a binary search for pages that
+/// are split between regions.
 #[derive(Debug)]
 pub struct MemoryMapper {
+    pages: Vec<u16>,
     regions: Vec<MemoryRegion>,
+    /// `(first, last, region)` for every run of addresses handled by the same
+    /// region, in address order.
+    spans: Vec<(usize, usize, usize)>,
+}
+
+impl Default for MemoryMapper {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MemoryMapper {
     pub fn new() -> Self {
         Self {
+            pages: vec![UNMAPPED; PAGES],
             regions: Vec::new(),
+            spans: Vec::new(),
         }
     }
 
-    pub fn add_region(&mut self, region: MemoryRegion) {
-        self.regions.push(region)
+    /// Adds a region, rejecting it if it overlaps one with the same priority.
+    pub fn add_region(&mut self, region: MemoryRegion) -> Result<(), MemoryMapperError> {
+        let clash = self.regions.iter()
+            .find(|other| other.priority == region.priority && other.overlaps(&region));
+
+        if let Some(other) = clash {
+            return Err(MemoryMapperError(format!(
+                "region {:#x?} overlaps region {:#x?} at the same priority ({})",
+                region.range,
+                other.range,
+                region.priority,
+            )));
+        }
+
+        self.regions.push(region);
+        self.rebuild();
+
+        Ok(())
+    }
+
+    fn rebuild(&mut self) {
+        let mut order: Vec<usize> = (0..self.regions.len()).collect();
+        order.sort_by_key(|i| self.regions[*i].priority);
+
+        // lay the regions over each other from the lowest priority up,
+        // cutting away whatever each one covers from those beneath it
+        let mut spans: Vec<(usize, usize, usize)> = Vec::new();
+        for i in order {
+            let range = &self.regions[i].range;
+            let (first, last) = (*range.start(), (*range.end()).min(0xffff));
+            if first > last {
+                continue;
+            }
+
+            let mut uncovered = Vec::with_capacity(spans.len() + 2);
+            for (start, end, region) in spans {
+                if end < first || last < start {
+                    uncovered.push((start, end, region));
+                    continue;
+                }
+
+                if start < first {
+                    uncovered.push((start, first - 1, region));
+                }
+
+                if last < end {
+                    uncovered.push((last + 1, end, region));
+                }
+            }
+
+            uncovered.push((first, last, i));
+            uncovered.sort_unstable_by_key(|(start, _, _)| *start);
+            spans = uncovered;
+        }
+
+        // spans don't overlap, so a page one of them covers entirely is
+        // touched by no other
+        self.pages.fill(UNMAPPED);
+        for (start, end, region) in spans.iter() {
+            for page in start / PAGE_SIZE..=end / PAGE_SIZE {
+                let first = page * PAGE_SIZE;
+                let last = first + PAGE_SIZE - 1;
+
+                self.pages[page] = if *start <= first && last <= *end {
+                    *region as u16
+                } else {
+                    MIXED
+                };
+            }
+        }
+
+        self.spans = spans;
+    }
+
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions
+    }
+
+    pub fn regions_mut(&mut self) -> &mut [MemoryRegion] {
+        &mut self.regions
+    }
+
+    fn index_of(&self, addr: Addr) -> Option<usize> {
+        lookup(&self.pages, &self.spans, addr)
+    }
+
+    /// Gives every device a turn at the bus, returning how many cycles
+    /// their accesses took, at `memory_access` each plus the `access_cycles`
+    /// of the region, and what they wrote.
+    pub(crate) fn run_bus(&mut self, memory_access: u64) -> (u64, Vec<BusWrite>) {
+        let mut cycles = 0;
+        let mut written = Vec::new();
+
+        for i in 0..self.regions.len() {
+            let (before, rest) = self.regions.split_at_mut(i);
+            let (own, after) = rest.split_first_mut().unwrap();
+
+            let mut bus = Bus {
+                accesses: 0,
+                after,
+                before,
+                cycles: 0,
+                memory_access,
+                pages: &self.pages,
+                spans: &self.spans,
+                written,
+            };
+
+            own.access_bus(&mut bus);
+
+            cycles += bus.cycles;
+            written = bus.written;
+        }
+
+        (cycles, written)
+    }
+
+    /// Whether reading or writing `addr` would reach a region, rather than
+    /// panicking.
+    pub fn is_mapped(&self, addr: Addr) -> bool {
+        self.index_of(addr).is_some_and(|i| self.regions[i].reaches(addr))
     }
 
     pub fn find_region_from_addr(&self, addr: Addr) -> &MemoryRegion {
-        self.regions.iter()
-            .find(|region| region.range.contains(&(addr as usize)))
-            .expect(&format!("no region with range containing {:#x?}", addr))
+        let i = self.index_of(addr)
+            .unwrap_or_else(|| panic!("no region with range containing {:#x?}", addr));
+
+        &self.regions[i]
     }
 
     pub fn find_region_from_addr_mut(&mut self, addr: Addr) -> &mut MemoryRegion {
-        self.regions.iter_mut()
-            .find(|region| region.range.contains(&(addr as usize)))
-            .expect(&format!("no region with range containing {:#x?}", addr))
+        let i = self.index_of(addr)
+            .unwrap_or_else(|| panic!("no region with range containing {:#x?}", addr));
+
+        &mut self.regions[i]
+    }
+}
+
+fn lookup(pages: &[u16], spans: &[(usize, usize, usize)], addr: Addr) -> Option<usize> {
+    match pages[addr as usize / PAGE_SIZE] {
+        UNMAPPED => None,
+        MIXED => {
+            let addr = addr as usize;
+            let i = spans.partition_point(|(_, end, _)| *end < addr);
+
+            spans.get(i)
+                .filter(|(start, _, _)| *start <= addr)
+                .map(|(_, _, region)| *region)
+        },
+        region => Some(region as usize),
+    }
+}
+
+/// A byte a device wrote over the `Bus`. `old` is what was there before, but
+/// only for RAM; devices aren't read to find out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct BusWrite {
+    pub addr: Addr,
+    pub old: Option<Byte>,
+    pub new: Byte,
+}
+
+/// The address space as a device sees it when it reads or writes memory
+/// itself, e.g. for DMA. Every region but the device's own can be reached;
+/// each access costs the `Cpu` the same cycles as a program's would.
+pub struct Bus<'a> {
+    accesses: u64,
+    after: &'a mut [MemoryRegion],
+    before: &'a mut [MemoryRegion],
+    cycles: u64,
+    memory_access: u64,
+    pages: &'a [u16],
+    spans: &'a [(usize, usize, usize)],
+    written: Vec<BusWrite>,
+}
+
+impl Bus<'_> {
+    fn region(&mut self, addr: Addr) -> Result<&mut MemoryRegion, DeviceFault> {
+        let i = lookup(self.pages, self.spans, addr)
+            .ok_or_else(|| DeviceFault::new(format!("no region with range containing {:#x?}", addr)))?;
+
+        let own = self.before.len();
+
+        match i.cmp(&own) {
+            std::cmp::Ordering::Less => Ok(&mut self.before[i]),
+            std::cmp::Ordering::Greater => Ok(&mut self.after[i - own - 1]),
+            std::cmp::Ordering::Equal => Err(DeviceFault::new(format!(
+                "a device can't reach itself over the bus, at {:#x?}",
+                addr,
+            ))),
+        }
+    }
+
+    pub fn get_u8(&mut self, addr: Addr) -> Result<Byte, DeviceFault> {
+        let region = self.region(addr)?;
+        let val = region.get_u8(addr);
+        let wait = region.access_cycles();
+
+        self.charge(wait);
+
+        Ok(val)
+    }
+
+    pub fn set_u8(&mut self, addr: Addr, val: Byte) -> Result<(), DeviceFault> {
+        let region = self.region(addr)?;
+        if region.read_only() {
+            return Err(read_only_fault(addr));
+        }
+
+        let old = if region.is_memory() { Some(region.get_u8(addr)) } else { None };
+
+        region.set_u8(addr, val);
+        let wait = region.access_cycles();
+
+        self.charge(wait);
+        self.written.push(BusWrite { addr, old, new: val });
+
+        Ok(())
+    }
+
+    fn charge(&mut self, wait: u64) {
+        self.accesses += 1;
+        self.cycles += self.memory_access + wait;
+    }
+
+    /// How many reads and writes have been made so far.
+    pub fn accesses(&self) -> u64 {
+        self.accesses
+    }
+}
+
+pub(crate) fn read_only_fault(addr: Addr) -> DeviceFault {
+    DeviceFault::new(format!("cannot write to read-only memory at {:#06x?}", addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+
+    #[derive(Debug)]
+    struct Fixed(Short);
+
+    impl Read for Fixed {
+        fn get_u8(&self, _addr: Addr) -> Byte { self.0 as Byte }
+        fn get_u16(&self, _addr: Addr) -> Short { self.0 }
+    }
+
+    impl Write for Fixed {
+        fn set_u8(&mut self, _addr: Addr, _val: Byte) {}
+        fn set_u16(&mut self, _addr: Addr, _val: Short) {}
+    }
+
+    impl Device for Fixed {}
+
+    fn fixed(range: RangeInclusive<usize>, priority: u8, val: Short) -> MemoryRegion {
+        MemoryRegion::builder()
+            .range(range)
+            .priority(priority)
+            .device(Box::new(Fixed(val)))
+            .finalize()
+            .unwrap()
+    }
+
+    #[test]
+    fn can_resolve_priority() {
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(fixed(0x0000..=0xffff, 0, 0x0000)).unwrap();
+        mm.add_region(fixed(0x3000..=0x30ff, 1, 0x1111)).unwrap();
+        mm.add_region(fixed(0x3010..=0x3011, 2, 0x2222)).unwrap();
+
+        // whole pages
+        assert_eq!(mm.find_region_from_addr(0x0000).get_u16(0x0000), 0x0000);
+        assert_eq!(mm.find_region_from_addr(0x3000).get_u16(0x3000), 0x1111);
+
+        // split pages
+        assert_eq!(mm.find_region_from_addr(0x300f).get_u16(0x300f), 0x1111);
+        assert_eq!(mm.find_region_from_addr(0x3010).get_u16(0x3010), 0x2222);
+        assert_eq!(mm.find_region_from_addr(0x3011).get_u16(0x3011), 0x2222);
+        assert_eq!(mm.find_region_from_addr(0x3012).get_u16(0x3012), 0x1111);
+        assert_eq!(mm.find_region_from_addr(0x30ff).get_u16(0x30ff), 0x1111);
+        assert_eq!(mm.find_region_from_addr(0x3100).get_u16(0x3100), 0x0000);
+    }
+
+    #[test]
+    fn can_resolve_priority_in_any_order() {
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(fixed(0x3010..=0x3011, 2, 0x2222)).unwrap();
+        mm.add_region(fixed(0x2f80..=0x30ff, 1, 0x1111)).unwrap();
+        mm.add_region(fixed(0x0000..=0x10000, 0, 0x0000)).unwrap();
+
+        let expected = |addr: Addr| match addr {
+            0x3010..=0x3011 => 0x2222,
+            0x2f80..=0x30ff => 0x1111,
+            _ => 0x0000,
+        };
+
+        for addr in 0..=0xffff {
+            assert_eq!(mm.find_region_from_addr(addr).get_u16(addr), expected(addr), "{:#x?}", addr);
+        }
+    }
+
+    #[test]
+    fn can_reject_overlap() {
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(fixed(0x1000..=0x1fff, 0, 0x0000)).unwrap();
+        assert!(mm.add_region(fixed(0x1fff..=0x2000, 0, 0x1111)).is_err());
+        assert!(mm.add_region(fixed(0x2000..=0x2fff, 0, 0x1111)).is_ok());
+        assert_eq!(mm.regions().len(), 2);
+    }
+
+    #[derive(Debug)]
+    struct Echo;
+
+    impl Read for Echo {
+        fn get_u8(&self, addr: Addr) -> Byte { addr as Byte }
+        fn get_u16(&self, addr: Addr) -> Short { addr }
+    }
+
+    impl Write for Echo {
+        fn set_u8(&mut self, _addr: Addr, _val: Byte) {}
+        fn set_u16(&mut self, _addr: Addr, _val: Short) {}
+    }
+
+    impl Device for Echo {}
+
+    #[test]
+    fn can_remap() {
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x1000..=0x10ff)
+                .device(Box::new(Echo))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x2000..=0x20ff)
+                .should_remap(false)
+                .device(Box::new(Echo))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x3000..=0x30ff)
+                .memory(Memory::with_capacity(0x100))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x4000..=0x40ff)
+                .should_remap(false)
+                .memory(Memory::with_capacity(0x4100))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        // relative to the region by default
+        assert_eq!(mm.find_region_from_addr(0x1010).get_u16(0x1010), 0x0010);
+        assert_eq!(mm.find_region_from_addr(0x2010).get_u16(0x2010), 0x2010);
+
+        mm.find_region_from_addr_mut(0x3010).set_u16(0x3010, 0x1234);
+        assert_eq!(mm.find_region_from_addr(0x3010).save_state()[0x10], 0x12);
+
+        mm.find_region_from_addr_mut(0x4010).set_u16(0x4010, 0x1234);
+        assert_eq!(mm.find_region_from_addr(0x4010).save_state()[0x4010], 0x12);
+    }
+
+    #[test]
+    #[should_panic(expected = "no region with range containing 0x1080")]
+    fn cannot_find_unmapped() {
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(fixed(0x1000..=0x107f, 0, 0x0000)).unwrap();
+        mm.find_region_from_addr(0x1080);
+    }
+
+    #[test]
+    fn can_protect_rom() {
+        let mut rom = Memory::with_capacity(0x0100);
+
+        // move lit (0x1234) mem (0x0100), into RAM
+        // move lit (0x1234) mem (0x0000), over the program
+        rom.set_bytes(&[
+            MOV_LIT_MEM, 0x12, 0x34, 0x01, 0x00,
+            MOV_LIT_MEM, 0x12, 0x34, 0x00, 0x00,
+            HLT,
+        ]);
+
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x0000..=0x00ff)
+                .priority(1)
+                .read_only(true)
+                .memory(rom)
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x0000..=0xffff)
+                .memory(Memory::with_capacity(0x10000))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        let mut cpu = Cpu::from(mm);
+
+        assert_eq!(
+            cpu.run(),
+            StopReason::Fault(DeviceFault::new("cannot write to read-only memory at 0x0000")),
+        );
+        assert_eq!(cpu.get_u16(0x0100), 0x1234);
+        assert_eq!(cpu.get_u8(0x0000), MOV_LIT_MEM);
+
+        // the host can still patch it
+        cpu.set_u8(0x0000, HLT);
+        assert_eq!(cpu.get_u8(0x0000), HLT);
     }
 }
@@ -0,0 +1,157 @@
+use crate::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MemoryAccess {
+    Byte(Byte),
+    Short(Short),
+}
+
+/// Hooks called by `Cpu` while it executes. Every method has a no-op default,
+/// so implementors only override the events they care about.
+///
+/// Only accesses made by the program itself are reported; reads and writes
+/// through `Cpu`'s own `Read`/`Write` impls are left unobserved so that tools
+/// can inspect memory without disturbing the observer.
+pub trait CpuObserver: std::fmt::Debug {
+    fn before_execute(&mut self, _ip: Addr, _instruction: InstructionVariant) {}
+    fn after_execute(&mut self, _ip: Addr, _instruction: InstructionVariant) {}
+
+    fn on_read(&mut self, _addr: Addr, _access: MemoryAccess) {}
+    fn on_write(&mut self, _addr: Addr, _access: MemoryAccess) {}
+
+    fn on_register_write(&mut self, _reg: RegisterVariant, _old: Short, _new: Short) {}
+
+    /// Called once `stack_push_state` has saved the caller's state. `fp` is
+    /// the new frame pointer and `return_addr` the caller's `ip`.
+    fn on_call(&mut self, _return_addr: Addr, _fp: Addr) {}
+
+    /// Called once `stack_pop_state` has restored the caller's state.
+    fn on_return(&mut self, _return_addr: Addr) {}
+}
+
+/// Lets an embedder keep a handle on the observer it attached to a `Cpu`.
+impl<O: CpuObserver> CpuObserver for Rc<RefCell<O>> {
+    fn before_execute(&mut self, ip: Addr, instruction: InstructionVariant) {
+        self.borrow_mut().before_execute(ip, instruction);
+    }
+
+    fn after_execute(&mut self, ip: Addr, instruction: InstructionVariant) {
+        self.borrow_mut().after_execute(ip, instruction);
+    }
+
+    fn on_read(&mut self, addr: Addr, access: MemoryAccess) {
+        self.borrow_mut().on_read(addr, access);
+    }
+
+    fn on_write(&mut self, addr: Addr, access: MemoryAccess) {
+        self.borrow_mut().on_write(addr, access);
+    }
+
+    fn on_register_write(&mut self, reg: RegisterVariant, old: Short, new: Short) {
+        self.borrow_mut().on_register_write(reg, old, new);
+    }
+
+    fn on_call(&mut self, return_addr: Addr, fp: Addr) {
+        self.borrow_mut().on_call(return_addr, fp);
+    }
+
+    fn on_return(&mut self, return_addr: Addr) {
+        self.borrow_mut().on_return(return_addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use crate::registers::constants::*;
+
+    #[derive(Debug, Default)]
+    struct Recorder {
+        executed: Vec<(Addr, InstructionVariant)>,
+        writes: Vec<(Addr, MemoryAccess)>,
+        registers: Vec<(RegisterVariant, Short, Short)>,
+        calls: Vec<(Addr, Addr)>,
+        returns: Vec<Addr>,
+    }
+
+    impl CpuObserver for Recorder {
+        fn before_execute(&mut self, ip: Addr, instruction: InstructionVariant) {
+            self.executed.push((ip, instruction));
+        }
+
+        fn on_write(&mut self, addr: Addr, access: MemoryAccess) {
+            self.writes.push((addr, access));
+        }
+
+        fn on_register_write(&mut self, reg: RegisterVariant, old: Short, new: Short) {
+            self.registers.push((reg, old, new));
+        }
+
+        fn on_call(&mut self, return_addr: Addr, fp: Addr) {
+            self.calls.push((return_addr, fp));
+        }
+
+        fn on_return(&mut self, return_addr: Addr) {
+            self.returns.push(return_addr);
+        }
+    }
+
+    #[test]
+    fn observer_sees_execution() {
+        // move lit (0x1234) reg (r1)
+        // move reg (r1) mem (addr 0x0100)
+        // push lit (0x0000)
+        // call subroutine (0x3000)
+        let mut bytes = vec![
+            MOV_LIT_REG, 0x12, 0x34, R1,
+            MOV_REG_MEM, R1, 0x01, 0x00,
+            PSH_LIT, 0x00, 0x00,
+            CAL_LIT, 0x30, 0x00,
+            HLT,
+        ];
+
+        // subroutine: push lit (0x5555)
+        bytes.resize(0x3000, 0);
+        bytes.extend_from_slice(&[
+            PSH_LIT, 0x55, 0x55,
+            RET,
+        ]);
+
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(&bytes);
+
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+
+        let mut cpu = Cpu::from(memory);
+        cpu.set_observer(Box::new(recorder.clone()));
+        cpu.run();
+
+        let recorder = recorder.borrow();
+
+        assert_eq!(recorder.executed, vec![
+            (0x0000, InstructionVariant::MoveLitReg),
+            (0x0004, InstructionVariant::MoveRegMem),
+            (0x0008, InstructionVariant::PushLit),
+            (0x000B, InstructionVariant::CallLit),
+            (0x3000, InstructionVariant::PushLit),
+            (0x3003, InstructionVariant::Ret),
+            (0x000E, InstructionVariant::Halt),
+        ]);
+
+        assert_eq!(recorder.writes[0], (0x0100, MemoryAccess::Short(0x1234)));
+
+        // fetching moves `ip` without a report; only the call does
+        assert_eq!(recorder.registers[0], (RegisterVariant::R1, 0x0000, 0x1234));
+        assert_eq!(recorder.registers[1].0, RegisterVariant::Sp);
+        assert!(recorder.registers.contains(&(RegisterVariant::Ip, 0x000E, 0x3000)));
+
+        assert_eq!(cpu.frames(), vec![]);
+
+        assert_eq!(recorder.calls.len(), 1);
+        assert_eq!(recorder.calls[0].0, 0x000E);
+        assert_eq!(recorder.returns, vec![ 0x000E ]);
+    }
+}
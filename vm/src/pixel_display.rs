@@ -0,0 +1,364 @@
+use crate::prelude::*;
+use std::io;
+
+/// The 16 standard terminal colours, as `0x0RGB`.
+const DEFAULT_PALETTE: [Short; 16] = [
+    0x0000, 0x0a00, 0x00a0, 0x0aa0, 0x000a, 0x0a0a, 0x00aa, 0x0aaa,
+    0x0555, 0x0f55, 0x05f5, 0x0ff5, 0x055f, 0x0f5f, 0x05ff, 0x0fff,
+];
+
+/// A bitmap display with a byte per pixel, each a palette index (only the
+/// lower four bits count), laid out a row at a time from the top left. After
+/// the pixels come 16 palette entries, a `Short` each holding a colour as
+/// `0x0RGB`, and then the present register: writing anything to it captures
+/// the current picture as a frame, so a run can be saved as an animation.
+/// Nothing is ever shown on screen; frames are exported as PPM or PNG images.
+#[derive(Clone, Debug)]
+pub struct PixelDisplay {
+    fault: Option<DeviceFault>,
+    frames: Vec<Vec<Byte>>,
+    height: u16,
+    palette: [Short; 16],
+    pixels: Vec<Byte>,
+    width: u16,
+}
+
+impl Default for PixelDisplay {
+    fn default() -> Self {
+        Self::new(128, 128)
+    }
+}
+
+impl PixelDisplay {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            fault: None,
+            frames: Vec::new(),
+            height,
+            palette: DEFAULT_PALETTE,
+            pixels: vec![0; width as usize * height as usize],
+            width,
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Where the palette starts, relative to the start of the display. It's
+    /// always even, so there's a spare byte after an odd number of pixels.
+    pub fn palette_addr(&self) -> Addr {
+        ((self.pixels.len() + 1) & !1) as Addr
+    }
+
+    /// Where the present register is, relative to the start of the display.
+    pub fn present_addr(&self) -> Addr {
+        self.palette_addr() + 2 * self.palette.len() as Addr
+    }
+
+    /// How many addresses the display takes up.
+    pub fn len(&self) -> usize {
+        self.present_addr() as usize + 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The palette index of every pixel, a row at a time.
+    pub fn pixels(&self) -> &[Byte] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u16, y: u16) -> Option<Byte> {
+        if x >= self.width {
+            return None;
+        }
+
+        self.pixels.get(y as usize * self.width as usize + x as usize).copied()
+    }
+
+    pub fn palette(&self) -> &[Short; 16] {
+        &self.palette
+    }
+
+    /// The pictures captured through the present register, already turned
+    /// into RGB.
+    pub fn frames(&self) -> &[Vec<Byte>] {
+        &self.frames
+    }
+
+    /// The current picture as 8-bit RGB triples.
+    pub fn to_rgb(&self) -> Vec<Byte> {
+        self.pixels.iter()
+            .flat_map(|index| {
+                let colour = self.palette[(*index & 0xf) as usize];
+
+                // 0x0RGB to 0xRR, 0xGG, 0xBB
+                [8, 4, 0].iter().map(move |shift| ((colour >> shift) & 0xf) as Byte * 0x11)
+            })
+            .collect()
+    }
+
+    pub fn write_ppm<W: io::Write>(&self, out: W) -> io::Result<()> {
+        write_ppm(self.width, self.height, &self.to_rgb(), out)
+    }
+
+    pub fn write_png<W: io::Write>(&self, out: W) -> io::Result<()> {
+        write_png(self.width, self.height, &self.to_rgb(), out)
+    }
+
+    fn fault(&mut self, addr: Addr) {
+        self.fault = Some(DeviceFault::new(format!(
+            "address {:#x?} is past the end of the {}x{} display",
+            addr,
+            self.width,
+            self.height,
+        )));
+    }
+}
+
+impl Read for PixelDisplay {
+    fn get_u8(&self, addr: Addr) -> Byte {
+        if addr < self.palette_addr() {
+            return self.pixels.get(addr as usize).copied().unwrap_or(0);
+        }
+
+        self.get_register_u8(addr)
+    }
+
+    fn get_u16(&self, addr: Addr) -> Short {
+        let palette = self.palette_addr();
+
+        if addr < palette {
+            return ((self.get_u8(addr) as Short) << 8) | self.get_u8(addr + 1) as Short;
+        }
+
+        self.palette.get((addr - palette) as usize / 2).copied().unwrap_or(0)
+    }
+}
+
+impl Write for PixelDisplay {
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        if addr < self.palette_addr() {
+            if let Some(pixel) = self.pixels.get_mut(addr as usize) {
+                *pixel = val;
+            }
+
+            return;
+        }
+
+        // palette entries and the present register take whole `Short`s
+        self.set_register_u8(addr, val);
+    }
+
+    fn set_u16(&mut self, addr: Addr, val: Short) {
+        let palette = self.palette_addr();
+        let present = self.present_addr();
+
+        if addr < palette {
+            self.set_u8(addr, (val >> 8) as Byte);
+            self.set_u8(addr + 1, val as Byte);
+        } else if addr < present {
+            self.palette[(addr - palette) as usize / 2] = val & 0x0fff;
+        } else if addr == present {
+            self.frames.push(self.to_rgb());
+        } else {
+            self.fault(addr);
+        }
+    }
+}
+
+impl WordRegisters for PixelDisplay {}
+
+impl Device for PixelDisplay {
+    fn save_state(&self) -> Vec<Byte> {
+        let mut state = self.pixels.clone();
+
+        for colour in self.palette.iter() {
+            state.extend_from_slice(&colour.to_be_bytes());
+        }
+
+        state
+    }
+
+    fn load_state(&mut self, state: &[Byte]) -> Result<(), SnapshotError> {
+        let expected = self.pixels.len() + 2 * self.palette.len();
+
+        if state.len() != expected {
+            return Err(SnapshotError::new(format!(
+                "expected {:#x?} bytes of display, found {:#x?}",
+                expected,
+                state.len(),
+            )));
+        }
+
+        let (pixels, palette) = state.split_at(self.pixels.len());
+
+        self.pixels.copy_from_slice(pixels);
+        for (colour, bytes) in self.palette.iter_mut().zip(palette.chunks(2)) {
+            *colour = Short::from_be_bytes([bytes[0], bytes[1]]);
+        }
+
+        Ok(())
+    }
+
+    fn take_fault(&mut self) -> Option<DeviceFault> {
+        self.fault.take()
+    }
+}
+
+/// Writes 8-bit RGB triples as a binary PPM image.
+pub fn write_ppm<W: io::Write>(width: u16, height: u16, rgb: &[Byte], mut out: W) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    out.write_all(rgb)
+}
+
+/// Writes 8-bit RGB triples as a PNG image. The image data is stored rather
+/// than compressed, which keeps this short at the cost of bigger files.
+pub fn write_png<W: io::Write>(width: u16, height: u16, rgb: &[Byte], mut out: W) -> io::Result<()> {
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, deflate, no filtering, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(&mut out, b"IHDR", &header)?;
+
+    // every scanline starts with its filter type, which is always none
+    let mut raw = Vec::with_capacity(rgb.len() + height as usize);
+    for line in rgb.chunks((width as usize * 3).max(1)) {
+        raw.push(0);
+        raw.extend_from_slice(line);
+    }
+
+    write_chunk(&mut out, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(&mut out, b"IEND", &[])
+}
+
+fn write_chunk<W: io::Write>(out: &mut W, kind: &[Byte; 4], data: &[Byte]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let crc = crc32(kind.iter().chain(data.iter()));
+    out.write_all(&crc.to_be_bytes())
+}
+
+/// A zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[Byte]) -> Vec<Byte> {
+    let mut out = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+
+        out.push(last as Byte);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32<'a>(data: impl Iterator<Item = &'a Byte>) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[Byte]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_draw() {
+        let mut display = PixelDisplay::new(2, 2);
+        assert_eq!(display.len(), 4 + 32 + 2);
+
+        display.set_u16(0x0000, 0x0f01);
+        display.set_u8(0x0003, 0x09);
+        assert_eq!(display.pixel(0, 0), Some(0x0f));
+        assert_eq!(display.pixel(1, 1), Some(0x09));
+        assert_eq!(display.get_u16(0x0002), 0x0009);
+
+        // palette entry 1 to pure red
+        display.set_u16(display.palette_addr() + 2, 0x0f00);
+        assert_eq!(display.get_u16(display.palette_addr() + 2), 0x0f00);
+
+        assert_eq!(display.to_rgb(), vec![
+            0xff, 0xff, 0xff,  0xff, 0x00, 0x00,
+            0x00, 0x00, 0x00,  0xff, 0x55, 0x55,
+        ]);
+
+        let mut ppm = Vec::new();
+        display.write_ppm(&mut ppm).unwrap();
+        assert_eq!(&ppm[..11], b"P6\n2 2\n255\n");
+        assert_eq!(&ppm[11..], &display.to_rgb()[..]);
+    }
+
+    #[test]
+    fn can_capture_frames() {
+        let mut display = PixelDisplay::new(1, 1);
+
+        display.set_u16(display.present_addr(), 0);
+        display.set_u8(0x0000, 0x0f);
+        display.set_u16(display.present_addr(), 0);
+
+        assert_eq!(display.frames(), &[vec![0x00, 0x00, 0x00], vec![0xff, 0xff, 0xff]]);
+
+        display.set_u16(display.present_addr() + 2, 0);
+        assert!(display.take_fault().is_some());
+    }
+
+    #[test]
+    fn can_write_png() {
+        // the checksums from a well known 1x1 image
+        assert_eq!(crc32(b"IEND".iter()), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+
+        let mut png = Vec::new();
+        write_png(1, 1, &[0xff, 0x00, 0x00], &mut png).unwrap();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[png.len() - 12..], b"\x00\x00\x00\x00IEND\xae\x42\x60\x82");
+
+        // an uncompressed block holding a filter byte and one pixel
+        let idat = &png[33..];
+        assert_eq!(&idat[4..8], b"IDAT");
+        assert_eq!(&idat[8..10], &[0x78, 0x01]);
+        assert_eq!(&idat[10..19], &[0x01, 0x04, 0x00, 0xfb, 0xff, 0x00, 0xff, 0x00, 0x00]);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::prelude::*;
+use std::collections::BTreeMap;
+
+/// Instruction counts for one subroutine, keyed by its entry address.
+/// `inclusive` counts everything run while the subroutine was on the call
+/// stack, `exclusive` only what ran in its own body.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SubroutineProfile {
+    pub calls: u64,
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+/// A `CpuObserver` that counts executed instructions per address, per opcode
+/// and per subroutine, and keeps the call stacks they ran under.
+///
+/// Subroutines are identified by the first address run after a `cal`; the
+/// code that runs before any call is attributed to wherever execution
+/// started.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    by_addr: BTreeMap<Addr, u64>,
+    by_opcode: BTreeMap<Byte, u64>,
+    calling: bool,
+    stack: Vec<Addr>,
+    stacks: BTreeMap<Vec<Addr>, u64>,
+    subroutines: BTreeMap<Addr, SubroutineProfile>,
+    total: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn by_addr(&self) -> &BTreeMap<Addr, u64> {
+        &self.by_addr
+    }
+
+    /// Counts keyed by opcode; convert back with `InstructionVariant::from`.
+    pub fn by_opcode(&self) -> &BTreeMap<Byte, u64> {
+        &self.by_opcode
+    }
+
+    pub fn subroutines(&self) -> &BTreeMap<Addr, SubroutineProfile> {
+        &self.subroutines
+    }
+
+    /// Instruction counts per call stack, outermost subroutine first.
+    pub fn stacks(&self) -> &BTreeMap<Vec<Addr>, u64> {
+        &self.stacks
+    }
+}
+
+impl CpuObserver for Profiler {
+    fn before_execute(&mut self, ip: Addr, instruction: InstructionVariant) {
+        if self.stack.is_empty() || self.calling {
+            self.calling = false;
+            self.stack.push(ip);
+            self.subroutines.entry(ip).or_default().calls += 1;
+        }
+
+        self.total += 1;
+        *self.by_addr.entry(ip).or_default() += 1;
+        *self.by_opcode.entry(instruction.into()).or_default() += 1;
+
+        match self.stacks.get_mut(&self.stack[..]) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.stack.clone(), 1);
+            },
+        }
+
+        let current = *self.stack.last().unwrap();
+        self.subroutines.entry(current).or_default().exclusive += 1;
+
+        // recursive subroutines only count once per instruction
+        for (i, entry) in self.stack.iter().enumerate() {
+            if !self.stack[..i].contains(entry) {
+                self.subroutines.entry(*entry).or_default().inclusive += 1;
+            }
+        }
+    }
+
+    fn on_call(&mut self, _return_addr: Addr, _fp: Addr) {
+        self.calling = true;
+    }
+
+    fn on_return(&mut self, _return_addr: Addr) {
+        // a stray `ret` at the top level has nothing to return from
+        if self.stack.len() > 1 {
+            self.stack.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use crate::registers::constants::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn can_profile() {
+        // push lit (0x0000)
+        // call subroutine (0x3000)
+        let mut bytes = vec![
+            PSH_LIT, 0x00, 0x00,
+            CAL_LIT, 0x30, 0x00,
+            HLT,
+        ];
+
+        // subroutine: inc reg (r1)
+        bytes.resize(0x3000, 0);
+        bytes.extend_from_slice(&[
+            INC_REG, R1,
+            RET,
+        ]);
+
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(&bytes);
+
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+
+        let mut cpu = Cpu::from(memory);
+        cpu.set_observer(Box::new(profiler.clone()));
+        cpu.run();
+
+        let profiler = profiler.borrow();
+
+        assert_eq!(profiler.total(), 5);
+        assert_eq!(profiler.by_addr().get(&0x3000), Some(&1));
+        assert_eq!(profiler.by_opcode().get(&PSH_LIT), Some(&1));
+
+        assert_eq!(profiler.subroutines().get(&0x0000), Some(&SubroutineProfile {
+            calls: 1,
+            inclusive: 5,
+            exclusive: 3,
+        }));
+        assert_eq!(profiler.subroutines().get(&0x3000), Some(&SubroutineProfile {
+            calls: 1,
+            inclusive: 2,
+            exclusive: 2,
+        }));
+
+        assert_eq!(profiler.stacks().iter().collect::<Vec<_>>(), vec![
+            (&vec![ 0x0000 ], &3),
+            (&vec![ 0x0000, 0x3000 ], &2),
+        ]);
+    }
+}
@@ -16,6 +16,46 @@ pub enum RegisterVariant {
     Fp,
 }
 
+impl RegisterVariant {
+    pub const VARIANTS: [RegisterVariant; 12] = [
+        Self::Ip,
+        Self::Acc,
+        Self::R1,
+        Self::R2,
+        Self::R3,
+        Self::R4,
+        Self::R5,
+        Self::R6,
+        Self::R7,
+        Self::R8,
+        Self::Sp,
+        Self::Fp,
+    ];
+
+    /// Where this register sits in `VARIANTS`, and in the `Cpu`'s register
+    /// file.
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ip => "ip",
+            Self::Acc => "acc",
+            Self::R1 => "r1",
+            Self::R2 => "r2",
+            Self::R3 => "r3",
+            Self::R4 => "r4",
+            Self::R5 => "r5",
+            Self::R6 => "r6",
+            Self::R7 => "r7",
+            Self::R8 => "r8",
+            Self::Sp => "sp",
+            Self::Fp => "fp",
+        }
+    }
+}
+
 impl From<Byte> for RegisterVariant {
     fn from(i: Byte) -> Self {
         match i {
@@ -36,21 +76,21 @@ impl From<Byte> for RegisterVariant {
     }
 }
 
-impl Into<Byte> for RegisterVariant {
-    fn into(self) -> Byte {
-        match self {
-            Self::Ip => constants::IP,
-            Self::Acc => constants::ACC,
-            Self::R1 => constants::R1,
-            Self::R2 => constants::R2,
-            Self::R3 => constants::R3,
-            Self::R4 => constants::R4,
-            Self::R5 => constants::R5,
-            Self::R6 => constants::R6,
-            Self::R7 => constants::R7,
-            Self::R8 => constants::R8,
-            Self::Sp => constants::SP,
-            Self::Fp => constants::FP,
+impl From<RegisterVariant> for Byte {
+    fn from(val: RegisterVariant) -> Self {
+        match val {
+            RegisterVariant::Ip => constants::IP,
+            RegisterVariant::Acc => constants::ACC,
+            RegisterVariant::R1 => constants::R1,
+            RegisterVariant::R2 => constants::R2,
+            RegisterVariant::R3 => constants::R3,
+            RegisterVariant::R4 => constants::R4,
+            RegisterVariant::R5 => constants::R5,
+            RegisterVariant::R6 => constants::R6,
+            RegisterVariant::R7 => constants::R7,
+            RegisterVariant::R8 => constants::R8,
+            RegisterVariant::Sp => constants::SP,
+            RegisterVariant::Fp => constants::FP,
         }
     }
 }
@@ -60,6 +100,12 @@ pub struct Register {
     pub memory: Memory,
 }
 
+impl Default for Register {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Register {
     pub fn new() -> Self {
         Self {
@@ -0,0 +1,160 @@
+use crate::prelude::*;
+
+/// A random number generator, four bytes wide. The same seed always gives
+/// the same numbers, so a run can be repeated exactly; the numbers are
+/// nowhere near good enough for cryptography.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+    value: Short,
+}
+
+impl Rng {
+    /// The current random number. Reading doesn't change it; writing
+    /// anything moves on to the next one.
+    pub const VALUE: Addr = 0x0000;
+
+    /// Writing starts over from the written seed. Reads as zero.
+    pub const SEED: Addr = 0x0002;
+
+    /// How many addresses the device takes up.
+    pub fn len(&self) -> usize {
+        Self::SEED as usize + 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self {
+            state: seed,
+            value: 0,
+        };
+
+        rng.advance();
+        rng
+    }
+
+    /// Seeded from the host clock, for runs that don't need repeating.
+    pub fn from_time() -> Self {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        Self::new(now.as_nanos() as u64)
+    }
+
+    /// splitmix64, which copes with any seed, zero included.
+    fn advance(&mut self) {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        self.value = (z >> 48) as Short;
+    }
+}
+
+impl Read for Rng {
+    fn get_u8(&self, addr: Addr) -> Byte {
+        self.get_register_u8(addr)
+    }
+
+    fn get_u16(&self, addr: Addr) -> Short {
+        match addr {
+            Self::VALUE => self.value,
+            _ => 0,
+        }
+    }
+}
+
+impl Write for Rng {
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        // a seed written a byte at a time only takes the byte
+        self.set_u16(addr & !1, val as Short);
+    }
+
+    fn set_u16(&mut self, addr: Addr, val: Short) {
+        match addr {
+            Self::VALUE => self.advance(),
+            Self::SEED => *self = Self::new(val as u64),
+            _ => (),
+        }
+    }
+}
+
+impl WordRegisters for Rng {}
+
+impl Device for Rng {
+    fn save_state(&self) -> Vec<Byte> {
+        let mut state = self.state.to_be_bytes().to_vec();
+        state.extend_from_slice(&self.value.to_be_bytes());
+        state
+    }
+
+    fn load_state(&mut self, state: &[Byte]) -> Result<(), SnapshotError> {
+        if state.len() != 10 {
+            return Err(SnapshotError::new(format!(
+                "expected 0xa bytes of random number generator, found {:#x?}",
+                state.len(),
+            )));
+        }
+
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&state[..8]);
+
+        self.state = u64::from_be_bytes(bytes);
+        self.value = Short::from_be_bytes([state[8], state[9]]);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(rng: &mut Rng, count: usize) -> Vec<Short> {
+        (0..count)
+            .map(|_| {
+                let val = rng.get_u16(Rng::VALUE);
+                rng.set_u16(Rng::VALUE, 0);
+                val
+            })
+            .collect()
+    }
+
+    #[test]
+    fn can_repeat() {
+        let first = numbers(&mut Rng::new(42), 8);
+
+        assert_eq!(numbers(&mut Rng::new(42), 8), first);
+        assert_ne!(numbers(&mut Rng::new(43), 8), first);
+
+        // the numbers don't just repeat themselves
+        let mut sorted = first.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(sorted.len(), 8);
+
+        // reseeding from the program
+        let mut rng = Rng::from_time();
+        rng.set_u16(Rng::SEED, 42);
+        assert_eq!(numbers(&mut rng, 8), first);
+    }
+
+    #[test]
+    fn can_snapshot() {
+        let mut rng = Rng::new(7);
+        numbers(&mut rng, 3);
+
+        let mut copy = Rng::new(0);
+        copy.load_state(&rng.save_state()).unwrap();
+        assert_eq!(numbers(&mut copy, 4), numbers(&mut rng, 4));
+
+        assert!(copy.load_state(&[0; 3]).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug)]
+enum Clock {
+    Host,
+    Virtual {
+        cycles: u64,
+        cycles_per_second: u64,
+        start: u64,
+    },
+}
+
+/// A real-time clock, fourteen bytes wide, giving the date and time in UTC a
+/// field at a time. The fields only change when the program writes the latch
+/// register, so reading them one after another can't straddle a second.
+/// The time comes from the host, or from a virtual clock driven by the
+/// cycles the program spends, which makes runs repeatable.
+#[derive(Clone, Debug)]
+pub struct Rtc {
+    clock: Clock,
+    latched: u64,
+}
+
+impl Rtc {
+    /// The year, e.g. `2020`.
+    pub const YEAR: Addr = 0x0000;
+
+    /// The month, from 1.
+    pub const MONTH: Addr = 0x0002;
+
+    /// The day of the month, from 1.
+    pub const DAY: Addr = 0x0004;
+
+    pub const HOUR: Addr = 0x0006;
+
+    pub const MINUTE: Addr = 0x0008;
+
+    pub const SECOND: Addr = 0x000a;
+
+    /// Writing anything captures the current time into the other registers.
+    pub const LATCH: Addr = 0x000c;
+
+    /// How many addresses the device takes up.
+    pub fn len(&self) -> usize {
+        Self::LATCH as usize + 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Tells the time by the host's clock.
+    pub fn host() -> Self {
+        Self::with_clock(Clock::Host)
+    }
+
+    /// Starts at `start`, in seconds since 1970, and moves on a second every
+    /// `cycles_per_second` cycles.
+    pub fn virtual_clock(start: u64, cycles_per_second: u64) -> Self {
+        Self::with_clock(Clock::Virtual {
+            cycles: 0,
+            cycles_per_second: cycles_per_second.max(1),
+            start,
+        })
+    }
+
+    fn with_clock(clock: Clock) -> Self {
+        let mut rtc = Self {
+            clock,
+            latched: 0,
+        };
+
+        rtc.latch();
+        rtc
+    }
+
+    /// Seconds since 1970.
+    pub fn now(&self) -> u64 {
+        match self.clock {
+            Clock::Host => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs())
+                .unwrap_or(0),
+            Clock::Virtual { cycles, cycles_per_second, start } => start + cycles / cycles_per_second,
+        }
+    }
+
+    fn latch(&mut self) {
+        self.latched = self.now();
+    }
+}
+
+/// Splits seconds since 1970 into year, month, day, hour, minute and second.
+fn civil(seconds: u64) -> [Short; 6] {
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // Howard Hinnant's `civil_from_days`, with years starting in March so
+    // the leap day comes last
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as u64;
+
+    [
+        year as Short,
+        month as Short,
+        day as Short,
+        (time / 3600) as Short,
+        (time / 60 % 60) as Short,
+        (time % 60) as Short,
+    ]
+}
+
+impl Read for Rtc {
+    fn get_u8(&self, addr: Addr) -> Byte {
+        self.get_register_u8(addr)
+    }
+
+    fn get_u16(&self, addr: Addr) -> Short {
+        match addr {
+            Self::YEAR..=Self::SECOND if addr & 1 == 0 => civil(self.latched)[addr as usize / 2],
+            _ => 0,
+        }
+    }
+}
+
+impl Write for Rtc {
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        self.set_u16(addr & !1, val as Short);
+    }
+
+    fn set_u16(&mut self, addr: Addr, _val: Short) {
+        if addr == Self::LATCH {
+            self.latch();
+        }
+    }
+}
+
+impl WordRegisters for Rtc {}
+
+impl Device for Rtc {
+    fn save_state(&self) -> Vec<Byte> {
+        let cycles = match self.clock {
+            Clock::Host => 0,
+            Clock::Virtual { cycles, .. } => cycles,
+        };
+
+        [self.latched.to_be_bytes(), cycles.to_be_bytes()].concat()
+    }
+
+    fn load_state(&mut self, state: &[Byte]) -> Result<(), SnapshotError> {
+        if state.len() != 16 {
+            return Err(SnapshotError::new(format!(
+                "expected 0x10 bytes of clock, found {:#x?}",
+                state.len(),
+            )));
+        }
+
+        let mut bytes = [0; 8];
+
+        bytes.copy_from_slice(&state[..8]);
+        self.latched = u64::from_be_bytes(bytes);
+
+        // the host's clock carries on regardless
+        if let Clock::Virtual { cycles, .. } = &mut self.clock {
+            bytes.copy_from_slice(&state[8..]);
+            *cycles = u64::from_be_bytes(bytes);
+        }
+
+        Ok(())
+    }
+
+    fn tick(&mut self, spent: u64) {
+        if let Clock::Virtual { cycles, .. } = &mut self.clock {
+            *cycles += spent;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_split_dates() {
+        assert_eq!(civil(0), [1970, 1, 1, 0, 0, 0]);
+        assert_eq!(civil(951_782_400), [2000, 2, 29, 0, 0, 0]);
+        assert_eq!(civil(1_700_000_000), [2023, 11, 14, 22, 13, 20]);
+        assert_eq!(civil(4_102_444_799), [2099, 12, 31, 23, 59, 59]);
+    }
+
+    #[test]
+    fn can_keep_virtual_time() {
+        let mut rtc = Rtc::virtual_clock(1_700_000_000, 1000);
+        assert_eq!(rtc.get_u16(Rtc::SECOND), 20);
+
+        // nothing moves until the latch is written
+        rtc.tick(2500);
+        assert_eq!(rtc.get_u16(Rtc::SECOND), 20);
+
+        rtc.set_u8(Rtc::LATCH + 1, 0);
+        assert_eq!(rtc.get_u16(Rtc::SECOND), 22);
+        assert_eq!(rtc.get_u8(Rtc::YEAR), 0x07);
+        assert_eq!(rtc.get_u8(Rtc::YEAR + 1), 0xe7);
+
+        let mut copy = Rtc::virtual_clock(1_700_000_000, 1000);
+        copy.load_state(&rtc.save_state()).unwrap();
+        assert_eq!(copy.now(), rtc.now());
+    }
+
+    #[test]
+    fn can_tell_host_time() {
+        let rtc = Rtc::host();
+        assert!(rtc.get_u16(Rtc::YEAR) >= 2020);
+    }
+}
@@ -1,57 +1,214 @@
+use crate::framebuffer::colour_code;
 use crate::prelude::*;
-use std::io::{Stdin, Stdout};
-
+use std::io::Stdout;
+
+/// A character screen drawn with ANSI escapes. Every address is one cell,
+/// laid out a row at a time. A `Short` written to a cell holds a command in
+/// the upper byte and a character in the lower byte.
+///
+/// These commands take effect before the character is drawn:
+///
+/// | command       | effect                                  |
+/// |---------------|-----------------------------------------|
+/// | `0x00`        | nothing                                 |
+/// | `0x01`        | bold                                    |
+/// | `0x02`        | reset colours and styles                |
+/// | `0x03`        | underline                               |
+/// | `0x04`        | inverse                                 |
+/// | `0x10`-`0x1f` | foreground colour 0-15                  |
+/// | `0x20`-`0x2f` | background colour 0-15                  |
+/// | `0xff`        | clear the screen                        |
+///
+/// These draw nothing, and take the lower byte as their argument instead:
+///
+/// | command       | effect                                  |
+/// |---------------|-----------------------------------------|
+/// | `0x05`        | hide the cursor                         |
+/// | `0x06`        | show the cursor                         |
+/// | `0x07`        | scroll up by the argument, in rows      |
+/// | `0x08`        | scroll down by the argument, in rows    |
+/// | `0xfd`        | foreground colour 0-255 (the argument)  |
+/// | `0xfe`        | background colour 0-255 (the argument)  |
+///
+/// Writing a `Byte` draws it without a command. Unknown commands, cells off
+/// the screen and output that can't be written are device faults. What's on
+/// screen is kept in a `FramebufferScreen`, so reading a cell gives back its
+/// character.
 #[derive(Debug)]
-pub struct ScreenDevice(Stdin, Stdout);
+pub struct ScreenDevice<W = Stdout> {
+    fault: Option<DeviceFault>,
+    framebuffer: FramebufferScreen,
+    out: W,
+}
+
+impl Default for ScreenDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl ScreenDevice {
+    /// A 16 by 16 screen.
     pub fn new() -> Self {
-        let stdin = std::io::stdin();
-        let stdout = std::io::stdout();
-
-        Self(stdin, stdout)
+        Self::with_size(16, 16)
     }
-}
 
-impl Read for ScreenDevice {
-    fn get_u8(&self, _: Addr) -> Byte { unimplemented!(); }
-    fn get_u16(&self, _: Addr) -> Short { unimplemented!(); }
+    pub fn with_size(columns: u16, rows: u16) -> Self {
+        Self::with_output(columns, rows, std::io::stdout())
+    }
 }
 
-impl Write for ScreenDevice {
-    fn set_u8(&mut self, _: Addr, _: Byte) { unimplemented!(); }
-
-    fn set_u16(&mut self, addr: Addr, val: Short) {
-        use std::io::Write;
+impl<W: std::io::Write> ScreenDevice<W> {
+    /// A screen writing its escapes to `out` rather than stdout.
+    pub fn with_output(columns: u16, rows: u16, out: W) -> Self {
+        Self {
+            fault: None,
+            framebuffer: FramebufferScreen::new(columns, rows),
+            out,
+        }
+    }
 
-        // println!("{:#04x?}", val);
+    /// How many addresses the screen takes up, one per cell.
+    pub fn len(&self) -> usize {
+        self.framebuffer.len()
+    }
 
-        let command = ((val & 0xff00) >> 0b1000) as u8;
-        let char = (val & 0x00ff) as u8;
+    pub fn is_empty(&self) -> bool {
+        self.framebuffer.is_empty()
+    }
 
-        // println!("{:#02x?} {:#02x?}", command, char);
+    pub fn framebuffer(&self) -> &FramebufferScreen {
+        &self.framebuffer
+    }
 
-        let x = (addr % 0x10) as u8;
-        let y = (addr / 0x10) as u8;
+    pub fn output(&self) -> &W {
+        &self.out
+    }
 
-        let mut stdout = self.1.lock();
+    fn draw(&mut self, addr: Addr, command: ScreenCommand, val: Short) -> std::io::Result<()> {
+        let out = &mut self.out;
 
         match command {
-            0x00 => (),
-            0x01 => write!(stdout, "\x1B[1m").expect("cannot write to stdout"),
-            0x02 => write!(stdout, "\x1B[0m").expect("cannot write to stdout"),
-            0xff => write!(stdout, "\x1B[2J").expect("cannot write to stdout"),
-            _ => unimplemented!("unknown command `{:#02x?}`", command),
+            ScreenCommand::Plain => (),
+            ScreenCommand::Bold => write!(out, "\x1B[1m")?,
+            ScreenCommand::Reset => write!(out, "\x1B[0m")?,
+            ScreenCommand::Underline => write!(out, "\x1B[4m")?,
+            ScreenCommand::Inverse => write!(out, "\x1B[7m")?,
+            ScreenCommand::HideCursor => write!(out, "\x1B[?25l")?,
+            ScreenCommand::ShowCursor => write!(out, "\x1B[?25h")?,
+            ScreenCommand::ScrollUp(rows) => write!(out, "\x1B[{}S", rows)?,
+            ScreenCommand::ScrollDown(rows) => write!(out, "\x1B[{}T", rows)?,
+            ScreenCommand::Foreground(index) => write!(out, "\x1B[{}m", colour_code(index, true))?,
+            ScreenCommand::Background(index) => write!(out, "\x1B[{}m", colour_code(index, false))?,
+            ScreenCommand::Clear => write!(out, "\x1B[2J")?,
+        }
+
+        if !ScreenCommand::draws((val >> 0b1000) as Byte) {
+            return Ok(());
         }
 
+        let x = addr % self.framebuffer.columns();
+        let y = addr / self.framebuffer.columns();
+
         // move to x, y
-        write!(stdout, "\x1B[{};{}H", y + 1, (x + 1) * 2).expect("cannot write to stdout");
+        write!(out, "\x1B[{};{}H", y + 1, (x + 1) * 2)?;
 
         // write char
-        stdout.write(&[char]).expect("cannot write to stdout");
+        out.write_all(&[val as Byte])
+    }
+}
+
+impl<W> Read for ScreenDevice<W> {
+    fn get_u8(&self, addr: Addr) -> Byte { self.framebuffer.get_u8(addr) }
+    fn get_u16(&self, addr: Addr) -> Short { self.framebuffer.get_u16(addr) }
+}
+
+impl<W: std::io::Write> Write for ScreenDevice<W> {
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        self.set_u16(addr, val as Short);
+    }
+
+    fn set_u16(&mut self, addr: Addr, val: Short) {
+        let command = match self.framebuffer.write_cell(addr, val) {
+            Ok(command) => command,
+            Err(fault) => {
+                self.fault = Some(fault);
+                return;
+            },
+        };
+
+        let drawn = self.draw(addr, command, val).and_then(|_| self.out.flush());
+
+        if let Err(err) = drawn {
+            self.fault = Some(DeviceFault::new(format!("cannot write to the screen: {}", err)));
+        }
+    }
+}
 
-        stdout.flush().unwrap();
+impl<W: std::io::Write + std::fmt::Debug> Device for ScreenDevice<W> {
+    fn take_fault(&mut self) -> Option<DeviceFault> {
+        self.fault.take()
     }
 }
 
-impl Device for ScreenDevice {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(commands: &[(Addr, Short)]) -> (String, Option<DeviceFault>) {
+        let mut screen = ScreenDevice::with_output(4, 2, Vec::new());
+
+        let mut fault = None;
+        for (addr, val) in commands {
+            screen.set_u16(*addr, *val);
+            fault = fault.or(screen.take_fault());
+        }
+
+        (String::from_utf8(screen.out).unwrap(), fault)
+    }
+
+    #[test]
+    fn can_draw() {
+        let (out, fault) = draw(&[
+            (0x0000, 0xff00 | b'a' as Short),
+            (0x0005, 0x1100 | b'b' as Short),
+            (0x0006, 0xfd00 | 0xc4),
+            (0x0007, 0x0500),
+        ]);
+
+        assert_eq!(out, "\x1B[2J\x1B[1;2Ha\x1B[31m\x1B[2;4Hb\x1B[38;5;196m\x1B[?25l");
+        assert_eq!(fault, None);
+    }
+
+    #[test]
+    fn can_fault() {
+        let (out, fault) = draw(&[(0x0000, 0x4200 | b'a' as Short)]);
+        assert_eq!(out, "");
+        assert_eq!(fault, Some(DeviceFault::new("unknown screen command 0x42")));
+
+        let (out, fault) = draw(&[(0x0008, b'a' as Short)]);
+        assert_eq!(out, "");
+        assert_eq!(fault, Some(DeviceFault::new("cell 0x8 is off the 4x2 screen")));
+    }
+
+    #[test]
+    fn can_fault_on_closed_output() {
+        #[derive(Debug)]
+        struct Closed;
+
+        impl std::io::Write for Closed {
+            fn write(&mut self, _: &[Byte]) -> std::io::Result<usize> {
+                Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "closed"))
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut screen = ScreenDevice::with_output(4, 2, Closed);
+
+        screen.set_u16(0x0000, b'a' as Short);
+        assert_eq!(screen.take_fault(), Some(DeviceFault::new("cannot write to the screen: closed")));
+    }
+}
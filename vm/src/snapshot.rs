@@ -0,0 +1,286 @@
+use crate::prelude::*;
+use crate::registers::constants;
+use std::io::Read as _;
+use std::ops::RangeInclusive;
+
+const MAGIC: &[Byte] = b"LLJSSNAP";
+const VERSION: Byte = 0x02;
+
+#[derive(Debug)]
+pub struct SnapshotError(String);
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(err: std::io::Error) -> Self {
+        Self(err.to_string())
+    }
+}
+
+impl SnapshotError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RegionSnapshot {
+    pub range: RangeInclusive<usize>,
+    pub state: Vec<Byte>,
+}
+
+/// The complete state of a `Cpu`: registers, the hidden stack frame size,
+/// the cycles spent, the interrupt handler, and whatever each mapped device
+/// chose to save through `Device::save_state`.
+///
+/// On disk this is a big-endian stream starting with `LLJSSNAP` and a version
+/// byte. Restoring expects the `Cpu` to have the same memory layout the
+/// snapshot was taken from, since devices themselves can't be recreated from
+/// their saved state.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snapshot {
+    pub cycles: u64,
+    pub frame_size: Short,
+    pub interrupt_handler: Option<Addr>,
+    pub registers: Vec<(RegisterVariant, Short)>,
+    pub regions: Vec<RegionSnapshot>,
+}
+
+impl Snapshot {
+    pub fn write_to<W: std::io::Write>(&self, mut out: W) -> Result<(), SnapshotError> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[ VERSION ])?;
+
+        out.write_all(&self.frame_size.to_be_bytes())?;
+        out.write_all(&self.cycles.to_be_bytes())?;
+
+        match self.interrupt_handler {
+            Some(handler) => {
+                out.write_all(&[ 1 ])?;
+                out.write_all(&handler.to_be_bytes())?;
+            },
+            None => out.write_all(&[ 0, 0, 0 ])?,
+        }
+
+        out.write_all(&[ self.registers.len() as Byte ])?;
+        for (reg, val) in self.registers.iter() {
+            out.write_all(&[ (*reg).into() ])?;
+            out.write_all(&val.to_be_bytes())?;
+        }
+
+        out.write_all(&(self.regions.len() as u16).to_be_bytes())?;
+        for region in self.regions.iter() {
+            out.write_all(&(*region.range.start() as u32).to_be_bytes())?;
+            out.write_all(&(*region.range.end() as u32).to_be_bytes())?;
+            out.write_all(&(region.state.len() as u32).to_be_bytes())?;
+            out.write_all(&region.state)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: std::io::Read>(mut input: R) -> Result<Self, SnapshotError> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(SnapshotError::new("not a snapshot"));
+        }
+
+        let version = read_u8(&mut input)?;
+        if version != VERSION {
+            return Err(SnapshotError(format!("unsupported snapshot version `{}`", version)));
+        }
+
+        let frame_size = read_u16(&mut input)?;
+        let cycles = read_u64(&mut input)?;
+
+        let interrupt_handler = match (read_u8(&mut input)?, read_u16(&mut input)?) {
+            (0, _) => None,
+            (_, handler) => Some(handler),
+        };
+
+        let n_registers = read_u8(&mut input)?;
+        let mut registers = Vec::with_capacity(n_registers as usize);
+        for _ in 0..n_registers {
+            let reg = read_u8(&mut input)?;
+            if !(constants::IP..=constants::FP).contains(&reg) {
+                return Err(SnapshotError(format!("unknown register `{:#x?}`", reg)));
+            }
+
+            registers.push((RegisterVariant::from(reg), read_u16(&mut input)?));
+        }
+
+        let n_regions = read_u16(&mut input)?;
+        let mut regions = Vec::with_capacity(n_regions as usize);
+        for _ in 0..n_regions {
+            let start = read_u32(&mut input)? as usize;
+            let end = read_u32(&mut input)? as usize;
+
+            // the length can't be trusted, so read up to it rather than
+            // allocating it all up front
+            let len = read_u32(&mut input)? as usize;
+            let mut state = Vec::new();
+            input.by_ref().take(len as u64).read_to_end(&mut state)?;
+
+            if state.len() != len {
+                return Err(SnapshotError::new("snapshot ends in the middle of a region"));
+            }
+
+            regions.push(RegionSnapshot {
+                range: start..=end,
+                state,
+            });
+        }
+
+        Ok(Self {
+            cycles,
+            frame_size,
+            interrupt_handler,
+            registers,
+            regions,
+        })
+    }
+}
+
+fn read_u8<R: std::io::Read>(input: &mut R) -> Result<u8, SnapshotError> {
+    let mut buf = [0; 1];
+    input.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: std::io::Read>(input: &mut R) -> Result<u16, SnapshotError> {
+    let mut buf = [0; 2];
+    input.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32<R: std::io::Read>(input: &mut R) -> Result<u32, SnapshotError> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64<R: std::io::Read>(input: &mut R) -> Result<u64, SnapshotError> {
+    let mut buf = [0; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cannot_read_truncated_region() {
+        let snapshot = Snapshot {
+            cycles: 0,
+            frame_size: 0,
+            interrupt_handler: None,
+            registers: Vec::new(),
+            regions: vec![RegionSnapshot { range: 0..=0xff, state: vec![0; 0x100] }],
+        };
+
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+        assert_eq!(Snapshot::read_from(&bytes[..]).unwrap(), snapshot);
+
+        // claim 4 GiB of state, which mustn't be allocated before reading
+        let len = bytes.len() - 0x100 - 4;
+        bytes[len..len + 4].copy_from_slice(&[0xff; 4]);
+        assert!(Snapshot::read_from(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn can_snapshot() {
+        let bytes = include_bytes!("../tests/binary2");
+
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(bytes);
+
+        let mut cpu = Cpu::from(memory.clone());
+
+        cpu.step();
+        cpu.step();
+        cpu.step();
+
+        let mut saved = Vec::new();
+        cpu.snapshot().write_to(&mut saved).unwrap();
+
+        cpu.run();
+
+        let mut restored = Cpu::from(memory);
+        restored.restore(&Snapshot::read_from(&saved[..]).unwrap()).unwrap();
+        restored.run();
+
+        assert_eq!(restored.snapshot(), cpu.snapshot());
+        assert_eq!(restored.get_register_val(RegisterVariant::R2), 0x001E);
+    }
+
+    #[test]
+    fn cannot_restore_other_layout() {
+        let cpu = Cpu::from(Memory::with_capacity(0x100));
+        let mut other = Cpu::from(Memory::with_capacity(0x200));
+
+        assert!(other.restore(&cpu.snapshot()).is_err());
+    }
+
+    #[test]
+    fn cannot_restore_part_of_snapshot() {
+        let mut mm = MemoryMapper::new();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x0000..=0x00ff)
+                .memory(Memory::with_capacity(0x100))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        mm.add_region(
+            MemoryRegion::builder()
+                .range(0x0100..=0x0103)
+                .device(Box::new(Rng::new(1)))
+                .finalize()
+                .unwrap(),
+        ).unwrap();
+
+        let mut cpu = Cpu::from(mm);
+        cpu.set_u16(0x0000, 0x1234);
+
+        let mut snapshot = cpu.snapshot();
+        snapshot.regions[0].state[0] = 0xff;
+        snapshot.regions[1].state.pop();
+
+        // the memory before the generator would take its state happily
+        assert!(cpu.restore(&snapshot).is_err());
+        assert_eq!(cpu.get_u16(0x0000), 0x1234);
+    }
+
+    #[test]
+    fn can_snapshot_cycles_and_interrupt_handler() {
+        let bytes = include_bytes!("../tests/binary2");
+
+        let mut memory = Memory::with_capacity(0x10000);
+        memory.set_bytes(bytes);
+
+        let mut cpu = Cpu::from(memory.clone());
+        cpu.set_interrupt_handler(Some(0x0100));
+        cpu.step();
+
+        let mut saved = Vec::new();
+        cpu.snapshot().write_to(&mut saved).unwrap();
+
+        let mut restored = Cpu::from(memory);
+        restored.restore(&Snapshot::read_from(&saved[..]).unwrap()).unwrap();
+
+        assert_eq!(restored.cycles(), cpu.cycles());
+        assert_ne!(restored.cycles(), 0);
+        assert_eq!(restored.interrupt_handler(), Some(0x0100));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::prelude::*;
+
+/// How many cycles each instruction takes. An instruction costs its base
+/// cost, plus `memory_access` for every read or write of memory it makes on
+/// top of fetching itself (stack pushes and pops included), plus the
+/// `access_cycles` of the region each of those accesses lands in.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CycleCosts {
+    base: Vec<u64>,
+    pub memory_access: u64,
+}
+
+impl CycleCosts {
+    /// Every instruction costs `cycles` and memory accesses are free.
+    pub fn flat(cycles: u64) -> Self {
+        Self {
+            base: vec![cycles; 0x100],
+            memory_access: 0,
+        }
+    }
+
+    pub fn base(&self, variant: InstructionVariant) -> u64 {
+        let opcode: Byte = variant.into();
+        self.base[opcode as usize]
+    }
+
+    pub fn set_base(&mut self, variant: InstructionVariant, cycles: u64) {
+        let opcode: Byte = variant.into();
+        self.base[opcode as usize] = cycles;
+    }
+
+    pub fn with_base(mut self, variant: InstructionVariant, cycles: u64) -> Self {
+        self.set_base(variant, cycles);
+        self
+    }
+
+    pub fn with_memory_access(mut self, cycles: u64) -> Self {
+        self.memory_access = cycles;
+        self
+    }
+}
+
+impl Default for CycleCosts {
+    /// One cycle per byte fetched, two more for a multiply, and two per memory
+    /// access.
+    fn default() -> Self {
+        let mut costs = Self::flat(0).with_memory_access(2);
+
+        for variant in InstructionVariant::VARIANTS.iter().copied() {
+            let fetched = 1 + InstructionArguments::from(variant).bytes() as u64;
+
+            let extra = match variant {
+                InstructionVariant::MulLitReg |
+                InstructionVariant::MulRegReg => 2,
+                _ => 0,
+            };
+
+            costs.set_base(variant, fetched + extra);
+        }
+
+        costs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::constants::*;
+    use crate::registers::constants::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Debug)]
+    struct Clock(Rc<RefCell<u64>>);
+
+    impl Read for Clock {
+        fn get_u8(&self, _: Addr) -> Byte { 0 }
+        fn get_u16(&self, _: Addr) -> Short { 0 }
+    }
+
+    impl Write for Clock {
+        fn set_u8(&mut self, _: Addr, _: Byte) {}
+        fn set_u16(&mut self, _: Addr, _: Short) {}
+    }
+
+    impl Device for Clock {
+        fn tick(&mut self, cycles: u64) { *self.0.borrow_mut() += cycles; }
+    }
+
+    #[test]
+    fn can_count_cycles() {
+        let program = |costs: Option<CycleCosts>| {
+            let mut memory = Memory::with_capacity(0x10000);
+
+            // move lit (0x1234) reg (r1)
+            // move reg (r1) mem (addr 0x3000)
+            memory.set_bytes(&[
+                MOV_LIT_REG, 0x12, 0x34, R1,
+                MOV_REG_MEM, R1, 0x30, 0x00,
+                HLT,
+            ]);
+
+            let clock = Rc::new(RefCell::new(0));
+            let mut mm = MemoryMapper::new();
+
+            mm.add_region(
+                MemoryRegion::builder()
+                    .range(0x3000..=0x3001)
+                    .access_cycles(3)
+                    .priority(1)
+                    .memory(Memory::with_capacity(2))
+                    .finalize()
+                    .unwrap(),
+            ).unwrap();
+
+            mm.add_region(
+                MemoryRegion::builder()
+                    .range(0x4000..=0x4001)
+                    .priority(1)
+                    .device(Box::new(Clock(clock.clone())))
+                    .finalize()
+                    .unwrap(),
+            ).unwrap();
+
+            mm.add_region(
+                MemoryRegion::builder()
+                    .range(memory.get_range())
+                    .memory(memory)
+                    .finalize()
+                    .unwrap(),
+            ).unwrap();
+
+            let mut cpu = Cpu::from(mm);
+            if let Some(costs) = costs {
+                cpu.set_cycle_costs(costs);
+            }
+
+            cpu.run();
+
+            let ticked = *clock.borrow();
+            (cpu.cycles(), ticked)
+        };
+
+        // 4 for the first move, then 4 + 2 for the memory access + 3 for the
+        // slow region, then 1 to halt
+        assert_eq!(program(None), (14, 14));
+
+        let costs = CycleCosts::flat(1)
+            .with_base(InstructionVariant::Halt, 10);
+        assert_eq!(program(Some(costs)), (15, 15));
+    }
+}
@@ -0,0 +1,246 @@
+use crate::keyboard::spawn_reader;
+use crate::prelude::*;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+const RX_AVAILABLE: Short = 0b001;
+const RX_INTERRUPTS: Short = 0b010;
+const TX_READY: Short = 0b100;
+
+/// A serial port, six bytes wide: a byte stream in each direction with no
+/// notion of a cursor, unlike `ScreenDevice`. Received bytes are queued until
+/// the program takes them, and with interrupts enabled every byte that
+/// arrives interrupts the program.
+pub struct Uart {
+    fault: Option<DeviceFault>,
+    interrupts: bool,
+    pending: bool,
+    queue: VecDeque<Byte>,
+    rx: Option<Receiver<Byte>>,
+    tx: Box<dyn io::Write>,
+}
+
+impl std::fmt::Debug for Uart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Uart")
+            .field("interrupts", &self.interrupts)
+            .field("queue", &self.queue)
+            .finish()
+    }
+}
+
+impl Uart {
+    /// Bit 0: a byte is waiting in `RX`. Bit 1: receive interrupts are
+    /// enabled; write the bit to change it. Bit 2: `TX` can take a byte,
+    /// which it always can.
+    pub const STATUS: Addr = 0x0000;
+
+    /// The oldest byte received and not yet taken, or zero. Reading doesn't
+    /// take it; writing anything does.
+    pub const RX: Addr = 0x0002;
+
+    /// Writing sends the lower byte.
+    pub const TX: Addr = 0x0004;
+
+    /// How many addresses the device takes up.
+    pub fn len(&self) -> usize {
+        Self::TX as usize + 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// `rx` is `None` for a port that never receives anything.
+    pub fn new(rx: Option<Receiver<Byte>>, tx: Box<dyn io::Write>) -> Self {
+        Self {
+            fault: None,
+            interrupts: false,
+            pending: false,
+            queue: VecDeque::new(),
+            rx,
+            tx,
+        }
+    }
+
+    /// Receives whatever `reader` produces, read on a background thread,
+    /// and sends to `writer`.
+    pub fn from_io<R, W>(reader: Option<R>, writer: W) -> Self
+    where
+        R: io::Read + Send + 'static,
+        W: io::Write + 'static,
+    {
+        Self::new(reader.map(spawn_reader), Box::new(writer))
+    }
+
+    /// Connected to stdin and stdout.
+    pub fn stdio() -> Self {
+        Self::from_io(Some(io::stdin()), io::stdout())
+    }
+
+    /// Bytes received but not yet taken by the program.
+    pub fn queue(&self) -> &VecDeque<Byte> {
+        &self.queue
+    }
+
+    fn receive(&mut self) {
+        let rx = match self.rx.as_ref() {
+            Some(rx) => rx,
+            None => return,
+        };
+
+        loop {
+            match rx.try_recv() {
+                Ok(byte) => {
+                    self.queue.push_back(byte);
+                    self.pending |= self.interrupts;
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.rx = None;
+                    break;
+                },
+            }
+        }
+    }
+
+    fn send(&mut self, byte: Byte) {
+        let sent = self.tx.write_all(&[byte]).and_then(|_| self.tx.flush());
+
+        if let Err(err) = sent {
+            self.fault = Some(DeviceFault::new(format!("cannot send over the UART: {}", err)));
+        }
+    }
+
+    fn status(&self) -> Short {
+        let mut status = TX_READY;
+
+        if !self.queue.is_empty() {
+            status |= RX_AVAILABLE;
+        }
+
+        if self.interrupts {
+            status |= RX_INTERRUPTS;
+        }
+
+        status
+    }
+}
+
+impl Read for Uart {
+    fn get_u8(&self, addr: Addr) -> Byte {
+        self.get_register_u8(addr)
+    }
+
+    fn get_u16(&self, addr: Addr) -> Short {
+        match addr {
+            Self::STATUS => self.status(),
+            Self::RX => self.queue.front().copied().unwrap_or(0) as Short,
+            _ => 0,
+        }
+    }
+}
+
+impl Write for Uart {
+    fn set_u8(&mut self, addr: Addr, val: Byte) {
+        // only the low byte of any register means anything
+        if addr & 1 == 1 {
+            self.set_u16(addr & !1, val as Short);
+        }
+    }
+
+    fn set_u16(&mut self, addr: Addr, val: Short) {
+        match addr {
+            Self::STATUS => self.interrupts = val & RX_INTERRUPTS != 0,
+            Self::RX => {
+                self.queue.pop_front();
+            },
+            Self::TX => self.send(val as Byte),
+            _ => (),
+        }
+    }
+}
+
+impl WordRegisters for Uart {}
+
+impl Device for Uart {
+    fn tick(&mut self, _cycles: u64) {
+        self.receive();
+    }
+
+    fn take_interrupt(&mut self) -> bool {
+        std::mem::replace(&mut self.pending, false)
+    }
+
+    fn take_fault(&mut self) -> Option<DeviceFault> {
+        self.fault.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+
+    /// A writer the test can still look at after handing it over.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<Byte>>>);
+
+    impl io::Write for Shared {
+        fn write(&mut self, buf: &[Byte]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn can_send_and_receive() {
+        let (sender, receiver) = mpsc::channel();
+        let out = Shared::default();
+        let mut uart = Uart::new(Some(receiver), Box::new(out.clone()));
+
+        assert_eq!(uart.get_u16(Uart::STATUS), TX_READY);
+
+        uart.set_u16(Uart::TX, b'o' as Short);
+        uart.set_u8(Uart::TX + 1, b'k');
+        assert_eq!(&out.0.borrow()[..], b"ok");
+
+        uart.set_u16(Uart::STATUS, RX_INTERRUPTS);
+        sender.send(b'!').unwrap();
+        uart.tick(1);
+
+        assert_eq!(uart.get_u16(Uart::STATUS), TX_READY | RX_AVAILABLE | RX_INTERRUPTS);
+        assert_eq!(uart.get_u16(Uart::RX), b'!' as Short);
+        assert!(uart.take_interrupt());
+        assert!(!uart.take_interrupt());
+
+        uart.set_u16(Uart::RX, 0);
+        assert_eq!(uart.get_u16(Uart::RX), 0);
+    }
+
+    #[test]
+    fn can_fault() {
+        struct Closed;
+
+        impl io::Write for Closed {
+            fn write(&mut self, _: &[Byte]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut uart = Uart::from_io(None::<io::Empty>, Closed);
+
+        uart.set_u16(Uart::TX, b'x' as Short);
+        assert_eq!(uart.take_fault(), Some(DeviceFault::new("cannot send over the UART: closed")));
+    }
+}